@@ -1,4 +1,7 @@
 use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Barrier;
+use std::time::{Duration, Instant};
 
 // To reduce duplication, we use this macro. It only works because the API for
 // all the histogram types is roughly the same for some operations.
@@ -25,5 +28,136 @@ fn atomic(c: &mut Criterion) {
     benchmark!("atomic_histogram", histogram, c);
 }
 
-criterion_group!(benches, histogram, atomic);
+fn sharded(c: &mut Criterion) {
+    let histogram = histogram::ShardedHistogram::new(7, 64, 1).unwrap();
+    benchmark!("sharded_histogram", histogram, c);
+}
+
+// Compares the allocating percentile and conversion APIs with the variants
+// which write into caller-provided buffers.
+fn percentiles(c: &mut Criterion) {
+    let mut histogram = histogram::Histogram::new(7, 64).unwrap();
+    for value in 1..=100_000_u64 {
+        histogram.add(value * value, value % 7 + 1).unwrap();
+    }
+
+    let sorted = [25.0, 50.0, 75.0, 90.0, 99.0, 99.9, 99.99];
+    let unsorted = [99.0, 50.0, 99.99, 25.0, 90.0, 75.0, 99.9];
+
+    let mut group = c.benchmark_group("percentiles");
+
+    group.bench_function("percentiles/sorted", |b| {
+        b.iter(|| histogram.percentiles(&sorted))
+    });
+    group.bench_function("percentiles/unsorted", |b| {
+        b.iter(|| histogram.percentiles(&unsorted))
+    });
+
+    let mut buffer = Vec::with_capacity(sorted.len());
+    group.bench_function("percentiles_into/sorted", |b| {
+        b.iter(|| histogram.percentiles_into(&sorted, &mut buffer))
+    });
+    group.bench_function("percentiles_into/unsorted", |b| {
+        b.iter(|| histogram.percentiles_into(&unsorted, &mut buffer))
+    });
+
+    let sparse = histogram::SparseHistogram::from(&histogram);
+    group.bench_function("sparse/percentiles", |b| {
+        b.iter(|| sparse.percentiles(&sorted))
+    });
+    group.bench_function("sparse/percentiles_into", |b| {
+        b.iter(|| sparse.percentiles_into(&sorted, &mut buffer))
+    });
+
+    group.bench_function("sparse/from", |b| {
+        b.iter(|| histogram::SparseHistogram::from(&histogram))
+    });
+    let mut sparse = histogram::SparseHistogram::from(&histogram);
+    group.bench_function("sparse/copy_from", |b| {
+        b.iter(|| sparse.copy_from(&histogram))
+    });
+
+    let atomic = histogram::AtomicHistogram::new(7, 64).unwrap();
+    group.bench_function("atomic/load", |b| b.iter(|| atomic.load()));
+    let mut loaded = atomic.load();
+    group.bench_function("atomic/load_into", |b| {
+        b.iter(|| atomic.load_into(&mut loaded))
+    });
+
+    group.finish();
+}
+
+// Measures the time for several threads to concurrently increment the same
+// bucket, which is the worst case for contention on a shared counter. The
+// workers are spawned once and wait on a barrier for each batch of iterations,
+// and only their recording loops are timed.
+macro_rules! contended {
+    ($name:tt, $histogram:ident, $threads:ident, $group:ident) => {
+        let iters = AtomicU64::new(0);
+        let elapsed = AtomicU64::new(0);
+        let running = AtomicBool::new(true);
+        let start = Barrier::new($threads + 1);
+        let finish = Barrier::new($threads + 1);
+
+        std::thread::scope(|s| {
+            for _ in 0..$threads {
+                s.spawn(|| loop {
+                    start.wait();
+                    if !running.load(Ordering::Acquire) {
+                        break;
+                    }
+
+                    let iters = iters.load(Ordering::Acquire);
+                    let now = Instant::now();
+                    for _ in 0..iters {
+                        let _ = $histogram.increment(1);
+                    }
+                    elapsed.fetch_max(now.elapsed().as_nanos() as u64, Ordering::AcqRel);
+
+                    finish.wait();
+                });
+            }
+
+            $group.bench_function(format!("{}/{}", $name, $threads), |b| {
+                b.iter_custom(|n| {
+                    iters.store(n, Ordering::Release);
+                    elapsed.store(0, Ordering::Release);
+                    start.wait();
+                    finish.wait();
+                    Duration::from_nanos(elapsed.load(Ordering::Acquire))
+                })
+            });
+
+            running.store(false, Ordering::Release);
+            start.wait();
+        });
+    };
+}
+
+fn contention(c: &mut Criterion) {
+    let parallelism = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+
+    let mut group = c.benchmark_group("contended");
+    group.measurement_time(Duration::from_secs(10));
+
+    for threads in [1, 2, 4, 8, 16] {
+        if threads > parallelism {
+            break;
+        }
+
+        group.throughput(Throughput::Elements(threads as u64));
+
+        let atomic = histogram::AtomicHistogram::new(7, 64).unwrap();
+        contended!("atomic_histogram", atomic, threads, group);
+
+        let sharded = histogram::ShardedHistogram::new(7, 64, threads).unwrap();
+        contended!("sharded_histogram", sharded, threads, group);
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, histogram, atomic, sharded, percentiles, contention);
 criterion_main!(benches);
@@ -1,4 +1,8 @@
-use crate::{Config, Error, Histogram};
+use crate::exact::AtomicExact;
+use crate::{Config, Error, Histogram, Snapshot, Statistics};
+use clocksource::precise::{AtomicUnixInstant, UnixInstant};
+use core::ops::Deref;
+use core::ptr::NonNull;
 use core::sync::atomic::{AtomicU64, Ordering};
 
 /// A histogram that uses atomic 64bit counters for each bucket.
@@ -6,9 +10,44 @@ use core::sync::atomic::{AtomicU64, Ordering};
 /// Unlike the non-atomic variant, it cannot be used directly to report
 /// percentiles. Instead, a snapshot must be taken which captures the state of
 /// the histogram at a point in time.
+///
+/// The counters may also live in a region of shared memory, see
+/// [`create_shared`](AtomicHistogram::create_shared) and
+/// [`open_shared`](AtomicHistogram::open_shared).
 pub struct AtomicHistogram {
-    config: Config,
-    buckets: Box<[AtomicU64]>,
+    pub(crate) config: Config,
+    pub(crate) buckets: Buckets,
+    pub(crate) start: AtomicUnixInstant,
+    pub(crate) exact: Option<Box<AtomicExact>>,
+}
+
+/// The counters of an atomic histogram, which are either owned by the
+/// histogram or are in a region of memory provided by the caller.
+pub(crate) enum Buckets {
+    Owned(Box<[AtomicU64]>),
+    Shared(NonNull<AtomicU64>, usize),
+}
+
+// SAFETY: the counters are atomics, which may be used from any thread, and the
+// callers of `create_shared` and `open_shared` guarantee that a shared region
+// stays valid for as long as the histogram exists.
+unsafe impl Send for Buckets {}
+unsafe impl Sync for Buckets {}
+
+impl Deref for Buckets {
+    type Target = [AtomicU64];
+
+    fn deref(&self) -> &[AtomicU64] {
+        match self {
+            Self::Owned(buckets) => buckets,
+            // SAFETY: the pointer and length describe a region which the
+            // callers of `create_shared` and `open_shared` guarantee is valid
+            // for as long as the histogram exists
+            Self::Shared(buckets, len) => unsafe {
+                core::slice::from_raw_parts(buckets.as_ptr(), *len)
+            },
+        }
+    }
 }
 
 impl AtomicHistogram {
@@ -27,7 +66,23 @@ impl AtomicHistogram {
 
         Self {
             config: *config,
-            buckets: buckets.into(),
+            buckets: Buckets::Owned(buckets.into()),
+            start: AtomicUnixInstant::now(),
+            exact: None,
+        }
+    }
+
+    /// Creates a new atomic histogram using a provided [`crate::Config`] which
+    /// also tracks the exact sum, minimum, and maximum of recorded values. The
+    /// exact values are carried into the histograms produced by
+    /// [`load`](AtomicHistogram::load) and [`drain`](AtomicHistogram::drain).
+    /// See [`crate::Exact`] for details.
+    ///
+    /// Tracking exact values adds a few atomic operations to every increment.
+    pub fn with_exact(config: &Config) -> Self {
+        Self {
+            exact: Some(Box::new(AtomicExact::new())),
+            ..Self::with_config(config)
         }
     }
 
@@ -40,16 +95,51 @@ impl AtomicHistogram {
     pub fn add(&self, value: u64, count: u64) -> Result<(), Error> {
         let index = self.config.value_to_index(value)?;
         self.buckets[index].fetch_add(count, Ordering::Relaxed);
+        if let Some(exact) = &self.exact {
+            exact.add(value, count);
+        }
         Ok(())
     }
 
+    /// Returns the bucket configuration.
+    pub fn config(&self) -> Config {
+        self.config
+    }
+
+    /// Returns summary statistics for the current bucket values without
+    /// taking a snapshot. See [`crate::Statistics`] for details.
+    pub fn statistics(&self) -> Statistics {
+        let buckets = self
+            .buckets
+            .iter()
+            .map(|bucket| bucket.load(Ordering::Relaxed))
+            .enumerate();
+
+        let statistics = Statistics::from_buckets(&self.config, buckets);
+
+        match &self.exact {
+            Some(exact) => statistics.with_exact(&exact.load()),
+            None => statistics,
+        }
+    }
+
     // NOTE: once stabilized, `target_has_atomic_load_store` is more correct. https://github.com/rust-lang/rust/issues/94039
     #[cfg(target_has_atomic = "64")]
     /// Drains the bucket values into a new Histogram
     ///
     /// Unlike [`load`](AtomicHistogram::load), this method will reset all bucket values to zero. This uses [`AtomicU64::swap`] and is not available
     /// on platforms where [`AtomicU64::swap`] is not available.
+    ///
+    /// The start of the next snapshot is reset to the current time, see
+    /// [`drain_snapshot`](AtomicHistogram::drain_snapshot).
     pub fn drain(&self) -> Histogram {
+        self.start.store(UnixInstant::now(), Ordering::Relaxed);
+        self.drain_buckets()
+    }
+
+    #[cfg(target_has_atomic = "64")]
+    /// Drains the bucket values without resetting the start time.
+    fn drain_buckets(&self) -> Histogram {
         let buckets: Vec<u64> = self
             .buckets
             .iter()
@@ -59,6 +149,7 @@ impl AtomicHistogram {
         Histogram {
             config: self.config,
             buckets: buckets.into(),
+            exact: self.exact.as_ref().map(|exact| Box::new(exact.drain())),
         }
     }
 
@@ -73,7 +164,54 @@ impl AtomicHistogram {
         Histogram {
             config: self.config,
             buckets: buckets.into(),
+            exact: self.exact.as_ref().map(|exact| Box::new(exact.load())),
+        }
+    }
+
+    /// Read the bucket values into an existing `Histogram`, replacing its
+    /// contents. Unlike [`load`](AtomicHistogram::load), nothing is allocated.
+    ///
+    /// An error is returned if the histogram has a different configuration.
+    pub fn load_into(&self, histogram: &mut Histogram) -> Result<(), Error> {
+        if histogram.config != self.config {
+            return Err(Error::IncompatibleParameters);
+        }
+
+        for (bucket, counter) in histogram.buckets.iter_mut().zip(self.buckets.iter()) {
+            *bucket = counter.load(Ordering::Relaxed);
         }
+
+        // reuse the allocation for the exact values, if there is one
+        match (&mut histogram.exact, &self.exact) {
+            (Some(target), Some(exact)) => **target = exact.load(),
+            (target, exact) => *target = exact.as_ref().map(|exact| Box::new(exact.load())),
+        }
+
+        Ok(())
+    }
+
+    // NOTE: once stabilized, `target_has_atomic_load_store` is more correct. https://github.com/rust-lang/rust/issues/94039
+    #[cfg(target_has_atomic = "64")]
+    /// Drains the bucket values into a new [`crate::Snapshot`] which covers the
+    /// time since the histogram was created or last drained.
+    ///
+    /// See [`drain`](AtomicHistogram::drain) for details about draining.
+    pub fn drain_snapshot(&self) -> Snapshot {
+        let end = UnixInstant::now();
+        let start = self.start.swap(end, Ordering::Relaxed);
+
+        // the realtime clock may have stepped backwards since the last drain
+        Snapshot::new(start.min(end), end, self.drain_buckets()).unwrap()
+    }
+
+    /// Read the bucket values into a new [`crate::Snapshot`] which covers the
+    /// time since the histogram was created or last drained.
+    pub fn load_snapshot(&self) -> Snapshot {
+        let start = self.start.load(Ordering::Relaxed);
+        let histogram = self.load();
+        let end = UnixInstant::now().max(start);
+
+        Snapshot::new(start, end, histogram).unwrap()
     }
 }
 
@@ -84,7 +222,7 @@ mod test {
     #[cfg(target_pointer_width = "64")]
     #[test]
     fn size() {
-        assert_eq!(std::mem::size_of::<AtomicHistogram>(), 48);
+        assert_eq!(std::mem::size_of::<AtomicHistogram>(), 72);
     }
 
     #[cfg(target_has_atomic = "64")]
@@ -115,6 +253,82 @@ mod test {
         );
     }
 
+    #[cfg(target_has_atomic = "64")]
+    #[test]
+    /// Tests that snapshots cover consecutive time ranges
+    fn drain_snapshot() {
+        let histogram = AtomicHistogram::new(7, 64).unwrap();
+        histogram.increment(1).unwrap();
+
+        let loaded = histogram.load_snapshot();
+        let first = histogram.drain_snapshot();
+        assert_eq!(loaded.start(), first.start());
+        assert_eq!(loaded.histogram(), first.histogram());
+
+        histogram.increment(2).unwrap();
+        let second = histogram.drain_snapshot();
+        assert_eq!(first.end(), second.start());
+        assert_eq!(
+            second.histogram().percentile(100.0).unwrap().unwrap().end(),
+            2
+        );
+
+        let combined = first.checked_add(&second).unwrap();
+        assert_eq!(combined.histogram().as_slice().iter().sum::<u64>(), 2);
+    }
+
+    #[cfg(target_has_atomic = "64")]
+    #[test]
+    // Tests that a plain drain also resets the start of the next snapshot
+    fn drain_then_snapshot() {
+        let histogram = AtomicHistogram::new(7, 64).unwrap();
+        let created = histogram.load_snapshot().start();
+
+        histogram.increment(1).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let before = clocksource::precise::UnixInstant::now();
+        histogram.drain();
+
+        histogram.increment(2).unwrap();
+        let snapshot = histogram.drain_snapshot();
+        assert!(snapshot.start() > created);
+        assert!(snapshot.start() >= before);
+        assert_eq!(snapshot.histogram().as_slice().iter().sum::<u64>(), 1);
+    }
+
+    #[cfg(target_has_atomic = "64")]
+    #[test]
+    // Tests that exact values are carried into snapshots
+    fn exact() {
+        let histogram = AtomicHistogram::with_exact(&Config::new(2, 16).unwrap());
+        histogram.add(1001, 2).unwrap();
+        histogram.increment(7).unwrap();
+
+        let loaded = histogram.load().exact().unwrap();
+        assert_eq!(loaded.sum(), 2009);
+        assert_eq!(loaded.min(), Some(7));
+        assert_eq!(loaded.max(), Some(1001));
+        assert_eq!(histogram.statistics().sum(), 2009.0);
+
+        assert_eq!(histogram.drain().exact(), Some(loaded));
+        assert_eq!(histogram.load().exact(), Some(Exact::default()));
+        assert_eq!(AtomicHistogram::new(2, 16).unwrap().load().exact(), None);
+    }
+
+    #[test]
+    // Tests summary statistics
+    fn statistics() {
+        let histogram = AtomicHistogram::new(7, 64).unwrap();
+        for i in 0..=100 {
+            let _ = histogram.increment(i);
+        }
+
+        let statistics = histogram.statistics();
+        assert_eq!(statistics, histogram.load().statistics());
+        assert_eq!(statistics.count(), 101);
+        assert_eq!(statistics.mean(), Some(50.0));
+    }
+
     #[test]
     // Tests percentiles
     fn percentiles() {
@@ -194,4 +408,22 @@ mod test {
             }))
         );
     }
+
+    #[test]
+    // Tests loading into an existing histogram
+    fn load_into() {
+        let histogram = AtomicHistogram::with_exact(&Config::new(7, 64).unwrap());
+        let mut loaded = Histogram::new(7, 64).unwrap();
+        loaded.increment(1000).unwrap();
+
+        histogram.add(5, 3).unwrap();
+        histogram.load_into(&mut loaded).unwrap();
+        assert_eq!(loaded, histogram.load());
+
+        let mut other = Histogram::new(7, 32).unwrap();
+        assert_eq!(
+            histogram.load_into(&mut other),
+            Err(Error::IncompatibleParameters)
+        );
+    }
 }
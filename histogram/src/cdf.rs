@@ -0,0 +1,162 @@
+use crate::Config;
+use core::ops::RangeInclusive;
+
+/// A bucket along with the number of observations in it and in all of the
+/// buckets below it, as produced by the cumulative iterators.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CumulativeBucket {
+    pub(crate) count: u64,
+    pub(crate) cumulative: u128,
+    pub(crate) total: u128,
+    pub(crate) range: RangeInclusive<u64>,
+}
+
+impl CumulativeBucket {
+    /// Returns the number of observations within the bucket's range.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the number of observations within this bucket and all of the
+    /// buckets below it.
+    pub fn cumulative(&self) -> u128 {
+        self.cumulative
+    }
+
+    /// Returns the fraction of all observations which are within this bucket
+    /// or the buckets below it, in the range `0.0..=1.0`.
+    pub fn fraction(&self) -> f64 {
+        self.cumulative as f64 / self.total as f64
+    }
+
+    /// Returns the range for the bucket.
+    pub fn range(&self) -> RangeInclusive<u64> {
+        self.range.clone()
+    }
+
+    /// Returns the inclusive lower bound for the bucket.
+    pub fn start(&self) -> u64 {
+        *self.range.start()
+    }
+
+    /// Returns the inclusive upper bound for the bucket.
+    pub fn end(&self) -> u64 {
+        *self.range.end()
+    }
+}
+
+/// The counters backing a cumulative iterator.
+#[derive(Clone)]
+enum Counters<'a> {
+    Dense(&'a [u64]),
+    Sparse(&'a [usize], &'a [u64]),
+}
+
+/// An iterator across the non-empty buckets of a histogram which reports the
+/// running total of observations, which is the cumulative distribution.
+#[derive(Clone)]
+pub struct Cumulative<'a> {
+    config: Config,
+    counters: Counters<'a>,
+    position: usize,
+    cumulative: u128,
+    total: u128,
+}
+
+impl<'a> Cumulative<'a> {
+    pub(crate) fn dense(config: Config, buckets: &'a [u64]) -> Self {
+        Self::new(config, Counters::Dense(buckets))
+    }
+
+    pub(crate) fn sparse(config: Config, index: &'a [usize], count: &'a [u64]) -> Self {
+        Self::new(config, Counters::Sparse(index, count))
+    }
+
+    fn new(config: Config, counters: Counters<'a>) -> Self {
+        let total = match counters {
+            Counters::Dense(count) | Counters::Sparse(_, count) => {
+                count.iter().map(|count| *count as u128).sum()
+            }
+        };
+
+        Self {
+            config,
+            counters,
+            position: 0,
+            cumulative: 0,
+            total,
+        }
+    }
+}
+
+impl Iterator for Cumulative<'_> {
+    type Item = CumulativeBucket;
+
+    fn next(&mut self) -> Option<<Self as std::iter::Iterator>::Item> {
+        loop {
+            let (index, count) = match self.counters {
+                Counters::Dense(count) => (self.position, *count.get(self.position)?),
+                Counters::Sparse(index, count) => {
+                    (*index.get(self.position)?, count[self.position])
+                }
+            };
+
+            self.position += 1;
+
+            if count == 0 {
+                continue;
+            }
+
+            self.cumulative += count as u128;
+
+            return Some(CumulativeBucket {
+                count,
+                cumulative: self.cumulative,
+                total: self.total,
+                range: self.config.index_to_range(index),
+            });
+        }
+    }
+}
+
+/// Returns the number of observations in buckets whose values are all below
+/// the value, along with the total number of observations.
+pub(crate) fn count_below(
+    config: &Config,
+    buckets: impl Iterator<Item = (usize, u64)>,
+    value: u64,
+) -> (u128, u128) {
+    let mut below = 0;
+    let mut total = 0;
+
+    for (index, count) in buckets {
+        if config.index_to_upper_bound(index) < value {
+            below += count as u128;
+        }
+        total += count as u128;
+    }
+
+    (below, total)
+}
+
+/// Returns the number of observations in buckets which overlap the range, or
+/// zero if the range is empty.
+pub(crate) fn count_in_range(
+    config: &Config,
+    buckets: impl Iterator<Item = (usize, u64)>,
+    range: RangeInclusive<u64>,
+) -> u128 {
+    // a reversed range would otherwise overlap a bucket which contains both
+    // of its ends
+    if range.is_empty() {
+        return 0;
+    }
+
+    buckets
+        .filter(|(index, _)| {
+            config.index_to_lower_bound(*index) <= *range.end()
+                && config.index_to_upper_bound(*index) >= *range.start()
+        })
+        .map(|(_, count)| count as u128)
+        .sum()
+}
@@ -0,0 +1,431 @@
+use crate::{Config, Error, Histogram, PagedHistogram};
+use core::sync::atomic::{AtomicU16, AtomicU32, AtomicU64, Ordering};
+
+/// The width of the counters used to store the count for each bucket.
+///
+/// Narrower counters use less memory, which matters when there are many
+/// histograms that each hold few observations, such as one per connection.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CounterWidth {
+    /// 16bit counters, for up to 65535 observations in a bucket.
+    #[default]
+    U16,
+    /// 32bit counters, for up to about 4 billion observations in a bucket.
+    U32,
+    /// 64bit counters, as used by [`crate::Histogram`].
+    U64,
+}
+
+/// The counters of a compact histogram.
+#[derive(Clone, Debug, PartialEq)]
+enum Counters {
+    U16(Box<[u16]>),
+    U32(Box<[u32]>),
+    U64(Box<[u64]>),
+}
+
+impl Counters {
+    fn new(width: CounterWidth, len: usize) -> Self {
+        match width {
+            CounterWidth::U16 => Self::U16(vec![0; len].into()),
+            CounterWidth::U32 => Self::U32(vec![0; len].into()),
+            CounterWidth::U64 => Self::U64(vec![0; len].into()),
+        }
+    }
+
+    fn width(&self) -> CounterWidth {
+        match self {
+            Self::U16(_) => CounterWidth::U16,
+            Self::U32(_) => CounterWidth::U32,
+            Self::U64(_) => CounterWidth::U64,
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Self::U16(counters) => counters.len(),
+            Self::U32(counters) => counters.len(),
+            Self::U64(counters) => counters.len(),
+        }
+    }
+
+    fn get(&self, index: usize) -> u64 {
+        match self {
+            Self::U16(counters) => counters[index] as u64,
+            Self::U32(counters) => counters[index] as u64,
+            Self::U64(counters) => counters[index],
+        }
+    }
+
+    /// Adds to the counter, promoting all of the counters to a wider type if
+    /// the result would not fit. The widest counters wrap on overflow.
+    fn add(&mut self, index: usize, count: u64) {
+        match self {
+            Self::U16(counters) => {
+                if let Some(sum) = u16::try_from(count)
+                    .ok()
+                    .and_then(|count| counters[index].checked_add(count))
+                {
+                    counters[index] = sum;
+                    return;
+                }
+
+                *self = Self::U32(counters.iter().map(|count| *count as u32).collect());
+            }
+            Self::U32(counters) => {
+                if let Some(sum) = u32::try_from(count)
+                    .ok()
+                    .and_then(|count| counters[index].checked_add(count))
+                {
+                    counters[index] = sum;
+                    return;
+                }
+
+                *self = Self::U64(counters.iter().map(|count| *count as u64).collect());
+            }
+            Self::U64(counters) => {
+                counters[index] = counters[index].wrapping_add(count);
+                return;
+            }
+        }
+
+        self.add(index, count)
+    }
+}
+
+/// A histogram which stores its counts in narrow counters, which are widened
+/// for every bucket once any bucket's count would not fit.
+///
+/// It records the same buckets as a [`crate::Histogram`] and converts to one
+/// losslessly. Percentiles and the buckets are read through
+/// [`crate::ReadHistogram`]. With 16bit counters it uses a quarter of the
+/// memory until it is promoted.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompactHistogram {
+    config: Config,
+    counters: Counters,
+}
+
+impl CompactHistogram {
+    /// Construct a new compact histogram with 16bit counters from the provided
+    /// parameters. See the documentation for [`crate::Config`] to understand
+    /// their meaning.
+    pub fn new(grouping_power: u8, max_value_power: u8) -> Result<Self, Error> {
+        let config = Config::new(grouping_power, max_value_power)?;
+
+        Ok(Self::with_config(&config, CounterWidth::default()))
+    }
+
+    /// Creates a new compact histogram using a provided [`crate::Config`] with
+    /// counters which initially have the provided width.
+    pub fn with_config(config: &Config, width: CounterWidth) -> Self {
+        Self {
+            config: *config,
+            counters: Counters::new(width, config.total_buckets()),
+        }
+    }
+
+    /// Increment the counter for the bucket corresponding to the provided value
+    /// by one.
+    pub fn increment(&mut self, value: u64) -> Result<(), Error> {
+        self.add(value, 1)
+    }
+
+    /// Add some count to the counter for the bucket corresponding to the
+    /// provided value, widening the counters if needed.
+    pub fn add(&mut self, value: u64, count: u64) -> Result<(), Error> {
+        let index = self.config.value_to_index(value)?;
+        self.counters.add(index, count);
+        Ok(())
+    }
+
+    /// Returns the current width of the counters.
+    pub fn width(&self) -> CounterWidth {
+        self.counters.width()
+    }
+
+    /// Returns the bucket configuration of the histogram.
+    pub fn config(&self) -> Config {
+        self.config
+    }
+
+    /// Returns the count of the bucket with the index, which is the position
+    /// of the bucket in [`Histogram::as_slice`].
+    pub(crate) fn count(&self, index: usize) -> u64 {
+        self.counters.get(index)
+    }
+
+    /// Returns the number of buckets.
+    pub(crate) fn len(&self) -> usize {
+        self.counters.len()
+    }
+}
+
+impl From<&CompactHistogram> for Histogram {
+    fn from(other: &CompactHistogram) -> Self {
+        let mut histogram = Histogram::with_config(&other.config);
+
+        for (index, bucket) in histogram.buckets.iter_mut().enumerate() {
+            *bucket = other.count(index);
+        }
+
+        histogram
+    }
+}
+
+/// The counters of a compact atomic histogram.
+enum AtomicCounters {
+    U16(Box<[AtomicU16]>),
+    U32(Box<[AtomicU32]>),
+    U64(Box<[AtomicU64]>),
+}
+
+/// A histogram with narrow atomic counters, where any count which would not
+/// fit is carried in 64bit counters which are allocated as needed.
+///
+/// Atomic counters cannot all be widened at once while other threads are
+/// recording, so instead each bucket is promoted on its own. When adding to a
+/// narrow counter would overflow, the count is added to the bucket's counter
+/// in a [`crate::PagedHistogram`] instead, and the two are summed when the
+/// histogram is read. The overflow counters are only allocated for the pages
+/// of buckets which have overflowed.
+///
+/// Percentiles and the buckets are read through [`crate::ReadHistogram`], and
+/// [`load`](CompactAtomicHistogram::load) takes a snapshot which converts
+/// losslessly to the 64bit counters of a [`crate::Histogram`].
+pub struct CompactAtomicHistogram {
+    config: Config,
+    counters: AtomicCounters,
+    overflow: PagedHistogram,
+}
+
+impl CompactAtomicHistogram {
+    /// Construct a new compact atomic histogram with 16bit counters from the
+    /// provided parameters. See the documentation for [`crate::Config`] to
+    /// understand their meaning.
+    pub fn new(grouping_power: u8, max_value_power: u8) -> Result<Self, Error> {
+        let config = Config::new(grouping_power, max_value_power)?;
+
+        Ok(Self::with_config(&config, CounterWidth::default()))
+    }
+
+    /// Creates a new compact atomic histogram using a provided
+    /// [`crate::Config`] with counters of the provided width.
+    pub fn with_config(config: &Config, width: CounterWidth) -> Self {
+        let len = config.total_buckets();
+
+        let counters = match width {
+            CounterWidth::U16 => AtomicCounters::U16((0..len).map(|_| AtomicU16::new(0)).collect()),
+            CounterWidth::U32 => AtomicCounters::U32((0..len).map(|_| AtomicU32::new(0)).collect()),
+            CounterWidth::U64 => AtomicCounters::U64((0..len).map(|_| AtomicU64::new(0)).collect()),
+        };
+
+        Self {
+            config: *config,
+            counters,
+            overflow: PagedHistogram::with_config(config),
+        }
+    }
+
+    /// Increment the bucket that contains the value by one.
+    pub fn increment(&self, value: u64) -> Result<(), Error> {
+        self.add(value, 1)
+    }
+
+    /// Increment the bucket that contains the value by some count.
+    pub fn add(&self, value: u64, count: u64) -> Result<(), Error> {
+        let index = self.config.value_to_index(value)?;
+
+        // add to the narrow counter if the result fits
+        let added = match &self.counters {
+            AtomicCounters::U16(counters) => u16::try_from(count).ok().is_some_and(|count| {
+                counters[index]
+                    .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |v| {
+                        v.checked_add(count)
+                    })
+                    .is_ok()
+            }),
+            AtomicCounters::U32(counters) => u32::try_from(count).ok().is_some_and(|count| {
+                counters[index]
+                    .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |v| {
+                        v.checked_add(count)
+                    })
+                    .is_ok()
+            }),
+            AtomicCounters::U64(counters) => {
+                counters[index].fetch_add(count, Ordering::Relaxed);
+                true
+            }
+        };
+
+        if !added {
+            self.overflow.add_to_index(index, count);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the width of the narrow counters.
+    pub fn width(&self) -> CounterWidth {
+        match self.counters {
+            AtomicCounters::U16(_) => CounterWidth::U16,
+            AtomicCounters::U32(_) => CounterWidth::U32,
+            AtomicCounters::U64(_) => CounterWidth::U64,
+        }
+    }
+
+    /// Returns the bucket configuration of the histogram.
+    pub fn config(&self) -> Config {
+        self.config
+    }
+
+    /// Returns the number of buckets whose counts have overflowed the narrow
+    /// counters and have 64bit counters allocated, which is a multiple of the
+    /// page size of the [`crate::PagedHistogram`].
+    pub fn promoted_buckets(&self) -> usize {
+        self.overflow.allocated_buckets()
+    }
+
+    // NOTE: once stabilized, `target_has_atomic_load_store` is more correct. https://github.com/rust-lang/rust/issues/94039
+    #[cfg(target_has_atomic = "64")]
+    /// Drains the bucket values into a new Histogram
+    ///
+    /// Unlike [`load`](CompactAtomicHistogram::load), this method will reset
+    /// all bucket values to zero. This uses atomic swaps and is not available
+    /// on platforms where they are not available.
+    pub fn drain(&self) -> Histogram {
+        let mut histogram = match &self.counters {
+            AtomicCounters::U16(counters) => self.read(counters, |counter| {
+                counter.swap(0, Ordering::Relaxed) as u64
+            }),
+            AtomicCounters::U32(counters) => self.read(counters, |counter| {
+                counter.swap(0, Ordering::Relaxed) as u64
+            }),
+            AtomicCounters::U64(counters) => {
+                self.read(counters, |counter| counter.swap(0, Ordering::Relaxed))
+            }
+        };
+
+        for (index, counter) in self.overflow.counters() {
+            histogram.buckets[index] =
+                histogram.buckets[index].wrapping_add(counter.swap(0, Ordering::Relaxed));
+        }
+
+        histogram
+    }
+
+    /// Read the bucket values into a new `Histogram`
+    pub fn load(&self) -> Histogram {
+        let mut histogram = match &self.counters {
+            AtomicCounters::U16(counters) => {
+                self.read(counters, |counter| counter.load(Ordering::Relaxed) as u64)
+            }
+            AtomicCounters::U32(counters) => {
+                self.read(counters, |counter| counter.load(Ordering::Relaxed) as u64)
+            }
+            AtomicCounters::U64(counters) => {
+                self.read(counters, |counter| counter.load(Ordering::Relaxed))
+            }
+        };
+
+        for (index, counter) in self.overflow.counters() {
+            histogram.buckets[index] =
+                histogram.buckets[index].wrapping_add(counter.load(Ordering::Relaxed));
+        }
+
+        histogram
+    }
+
+    fn read<T>(&self, counters: &[T], read: impl Fn(&T) -> u64) -> Histogram {
+        let mut histogram = Histogram::with_config(&self.config);
+
+        for (bucket, counter) in histogram.buckets.iter_mut().zip(counters) {
+            *bucket = read(counter);
+        }
+
+        histogram
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ReadHistogram;
+
+    #[test]
+    // Tests that counters are widened as needed without losing counts
+    fn promotion() {
+        let mut histogram = CompactHistogram::new(7, 64).unwrap();
+        let mut expected = Histogram::new(7, 64).unwrap();
+        assert_eq!(histogram.width(), CounterWidth::U16);
+
+        for (value, count, width) in [
+            (1, u16::MAX as u64, CounterWidth::U16),
+            (1000, 7, CounterWidth::U16),
+            (1, 1, CounterWidth::U32),
+            (2, u32::MAX as u64 + 1, CounterWidth::U64),
+            (2, u64::MAX, CounterWidth::U64),
+        ] {
+            histogram.add(value, count).unwrap();
+            expected.add(value, count).unwrap();
+            assert_eq!(histogram.width(), width);
+            assert_eq!(Histogram::from(&histogram), expected);
+            assert!(histogram.counts().eq(ReadHistogram::counts(&expected)));
+            assert_eq!(
+                ReadHistogram::percentiles(&histogram, &[0.0, 50.0, 100.0]),
+                expected.percentiles(&[0.0, 50.0, 100.0])
+            );
+        }
+
+        assert_eq!(histogram.add(u64::MAX, 1), Ok(()));
+        assert_eq!(
+            CompactHistogram::new(7, 32).unwrap().add(u64::MAX, 1),
+            Err(Error::OutOfRange)
+        );
+
+        let histogram =
+            CompactHistogram::with_config(&Config::new(7, 64).unwrap(), CounterWidth::U32);
+        assert_eq!(histogram.width(), CounterWidth::U32);
+    }
+
+    #[cfg(target_has_atomic = "64")]
+    #[test]
+    // Tests that counts which overflow the narrow atomic counters are kept
+    fn atomic() {
+        for width in [CounterWidth::U16, CounterWidth::U32, CounterWidth::U64] {
+            let config = Config::new(7, 64).unwrap();
+            let histogram = CompactAtomicHistogram::with_config(&config, width);
+            let mut expected = Histogram::with_config(&config);
+            assert_eq!(histogram.width(), width);
+
+            std::thread::scope(|s| {
+                for _ in 0..4 {
+                    s.spawn(|| {
+                        for _ in 0..20_000 {
+                            histogram.increment(5).unwrap();
+                        }
+                        histogram.add(1_000_000, u32::MAX as u64).unwrap();
+                    });
+                }
+            });
+
+            expected.add(5, 80_000).unwrap();
+            expected.add(1_000_000, 4 * u32::MAX as u64).unwrap();
+
+            assert_eq!(histogram.load(), expected);
+            assert_eq!(histogram.promoted_buckets() > 0, width != CounterWidth::U64);
+
+            // the overflow counters are included when reading
+            assert!(histogram.counts().eq(ReadHistogram::counts(&expected)));
+            assert_eq!(histogram.total_count(), expected.total_count());
+            assert_eq!(
+                ReadHistogram::percentiles(&histogram, &[0.0, 50.0, 100.0]),
+                expected.percentiles(&[0.0, 50.0, 100.0])
+            );
+            assert!(histogram.cumulative().eq(expected.cumulative()));
+
+            assert_eq!(histogram.drain(), expected);
+            assert_eq!(histogram.load(), Histogram::with_config(&config));
+        }
+    }
+}
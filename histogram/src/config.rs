@@ -55,10 +55,63 @@ use serde::{Deserialize, Serialize};
 /// # Constraints:
 /// * `max_value_power` must be in the range `0..=64`
 /// * `max_value_power` must be greater than `grouping_power
+/// * the total number of buckets must fit in a `u32`, which limits the
+///   `grouping_power` to at most `26` when `max_value_power` is `64`
+///
+/// # Logarithmic configurations
+/// The bucket boundaries above are all based on powers of two, so the relative
+/// error is always a power of two. A configuration created with
+/// [`Config::logarithmic`] instead has bucket boundaries which are powers of
+/// `(1 + a) / (1 - a)` for an arbitrary relative accuracy `a`, which matches
+/// the bucketing of DDSketch. Values which are small enough to be stored
+/// exactly are stored in linear buckets, as with the standard configuration.
+///
+/// The boundaries of the logarithmic buckets are calculated with the
+/// platform's floating point `ln` and `exp`, which are not guaranteed to give
+/// identical results on every platform. A value very close to a boundary may
+/// be in a different bucket on another platform, so a histogram which is
+/// encoded, or shared through memory, with a logarithmic configuration is
+/// only guaranteed to be interpreted identically on the same platform.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
-#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(into = "SerializedConfig", try_from = "SerializedConfig")
+)]
 pub struct Config {
+    grouping_power: u8,
+    max_value_power: u8,
+    cutoff_power: u8,
+    /// The number of linear buckets, which is also the smallest value stored
+    /// in the logarithmic buckets.
+    lower_bin_count: u32,
+    upper_bin_count: u32,
+    /// The exponent of the first logarithmic bucket's upper bound, or zero for
+    /// a standard configuration.
+    offset: i32,
+    /// The bits of the relative accuracy of a logarithmic configuration, or
+    /// zero for a standard configuration. Held as bits so the configuration
+    /// remains `Eq`.
+    relative_accuracy: u64,
+    /// The bits of the natural log of the ratio between consecutive bucket
+    /// boundaries of a logarithmic configuration, or zero for a standard
+    /// configuration. This is derived from the relative accuracy, but is kept
+    /// so that it is not recalculated for every value.
+    ln_gamma: u64,
+}
+
+/// The serialized form of a [`Config`]. This keeps the fields of earlier
+/// versions, even though some are now derived, so that configurations written
+/// by them can still be read. The relative accuracy is absent for a standard
+/// configuration.
+///
+/// The configuration is recreated from its parameters when it is read, and
+/// the bucket counts must match those of the recreated configuration.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename = "Config")]
+struct SerializedConfig {
     max: u64,
     grouping_power: u8,
     max_value_power: u8,
@@ -67,6 +120,82 @@ pub struct Config {
     lower_bin_count: u32,
     upper_bin_divisions: u32,
     upper_bin_count: u32,
+    #[serde(default)]
+    relative_accuracy: Option<f64>,
+}
+
+#[cfg(feature = "serde")]
+impl From<Config> for SerializedConfig {
+    fn from(config: Config) -> Self {
+        Self {
+            max: config.max(),
+            grouping_power: config.grouping_power,
+            max_value_power: config.max_value_power,
+            cutoff_power: config.cutoff_power,
+            cutoff_value: config.lower_bin_count as u64,
+            lower_bin_count: config.lower_bin_count,
+            upper_bin_divisions: match config.relative_accuracy() {
+                None => 1 << config.grouping_power,
+                Some(_) => 0,
+            },
+            upper_bin_count: config.upper_bin_count,
+            relative_accuracy: config.relative_accuracy(),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<SerializedConfig> for Config {
+    type Error = Error;
+
+    fn try_from(serialized: SerializedConfig) -> Result<Self, Error> {
+        let config = match serialized.relative_accuracy {
+            None => Config::new(serialized.grouping_power, serialized.max_value_power)?,
+            Some(accuracy) => Config::logarithmic(accuracy, serialized.max_value_power)?,
+        };
+
+        if serialized.lower_bin_count != config.lower_bin_count
+            || serialized.upper_bin_count != config.upper_bin_count
+        {
+            return Err(Error::IncompatibleParameters);
+        }
+
+        Ok(config)
+    }
+}
+
+#[cfg(feature = "schemars")]
+impl schemars::JsonSchema for Config {
+    fn schema_name() -> String {
+        SerializedConfig::schema_name()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        SerializedConfig::json_schema(gen)
+    }
+}
+
+/// Returns the inclusive upper bound of the logarithmic bucket with the
+/// exponent, which is `floor(gamma^exponent)`.
+fn boundary(ln_gamma: f64, exponent: i32) -> u64 {
+    // conversion from float saturates at `u64::MAX`
+    (exponent as f64 * ln_gamma).exp().floor() as u64
+}
+
+/// Returns the exponent of the logarithmic bucket containing the value, which
+/// is the smallest exponent whose boundary is not below the value.
+fn exponent(ln_gamma: f64, value: u64) -> i32 {
+    let mut exponent = ((value as f64).ln() / ln_gamma).ceil() as i32;
+
+    // correct for any rounding in the floating point estimate
+    while boundary(ln_gamma, exponent) < value {
+        exponent += 1;
+    }
+    while boundary(ln_gamma, exponent - 1) >= value {
+        exponent -= 1;
+    }
+
+    exponent
 }
 
 impl Config {
@@ -84,6 +213,15 @@ impl Config {
             return Err(Error::MaxPowerTooLow);
         }
 
+        // the bucket counts are held as u32, which limits the grouping power
+        if grouping_power > 30
+            || (max_value_power - grouping_power - 1) as u64 * (1 << grouping_power)
+                + (2 << grouping_power)
+                > u32::MAX as u64
+        {
+            return Err(Error::IncompatibleParameters);
+        }
+
         // the cutoff is the point at which the linear range divisions and the
         // logarithmic range subdivisions diverge.
         //
@@ -104,28 +242,118 @@ impl Config {
         let lower_bin_width = 2_u32.pow(0);
         let upper_bin_divisions = 2_u32.pow(grouping_power as u32);
 
-        let max = if max_value_power == 64 {
-            u64::MAX
-        } else {
-            2_u64.pow(max_value_power as u32)
-        };
-
         let lower_bin_count = (cutoff_value / lower_bin_width as u64) as u32;
         let upper_bin_count = (max_value_power - cutoff_power) as u32 * upper_bin_divisions;
 
         Ok(Self {
-            max,
             grouping_power,
             max_value_power,
             cutoff_power,
-            cutoff_value,
             lower_bin_count,
-            upper_bin_divisions,
             upper_bin_count,
+            offset: 0,
+            relative_accuracy: 0,
+            ln_gamma: 0,
+        })
+    }
+
+    /// Create a new logarithmic histogram `Config` where the values in each
+    /// bucket are within the provided relative accuracy of each other, as
+    /// with DDSketch. See the struct documentation [`crate::Config`] for the
+    /// meaning of `max_value_power` and its constraints.
+    ///
+    /// The relative accuracy must be in the exclusive range `0.0..1.0`. For
+    /// example, a relative accuracy of `0.01` means any value reported for a
+    /// bucket is within 1% of every value in the bucket.
+    ///
+    /// The bucket boundaries depend on the platform's floating point math, see
+    /// the struct documentation [`crate::Config`].
+    pub fn logarithmic(relative_accuracy: f64, max_value_power: u8) -> Result<Self, Error> {
+        if max_value_power > 64 {
+            return Err(Error::MaxPowerTooHigh);
+        }
+
+        if max_value_power == 0 {
+            return Err(Error::MaxPowerTooLow);
+        }
+
+        if relative_accuracy.is_nan() || relative_accuracy <= 0.0 || relative_accuracy >= 1.0 {
+            return Err(Error::IncompatibleParameters);
+        }
+
+        let gamma = (1.0 + relative_accuracy) / (1.0 - relative_accuracy);
+        let ln_gamma = gamma.ln();
+
+        if !ln_gamma.is_finite() || ln_gamma <= 0.0 {
+            return Err(Error::IncompatibleParameters);
+        }
+
+        let max = if max_value_power == 64 {
+            u64::MAX
+        } else {
+            2_u64.pow(max_value_power as u32) - 1
+        };
+
+        // below this value the logarithmic buckets would be narrower than one,
+        // so the values are stored exactly in linear buckets
+        let cutoff = (1.0 / (gamma - 1.0)).ceil();
+        if cutoff > u32::MAX as f64 {
+            return Err(Error::IncompatibleParameters);
+        }
+        let cutoff_value = (cutoff as u64).clamp(1, max);
+
+        // the exponents of the buckets are held as i32
+        if (max as f64).ln() / ln_gamma >= i32::MAX as f64 {
+            return Err(Error::IncompatibleParameters);
+        }
+
+        let offset = exponent(ln_gamma, cutoff_value);
+
+        let upper_bin_count = exponent(ln_gamma, max) as i64 - offset as i64 + 1;
+        if upper_bin_count + cutoff_value as i64 > u32::MAX as i64 {
+            return Err(Error::IncompatibleParameters);
+        }
+
+        // the grouping power with at least this precision, for callers which
+        // need a power of two
+        let grouping_power =
+            ((1.0 / relative_accuracy).log2().ceil() as u8).clamp(0, max_value_power - 1);
+
+        Ok(Self {
+            grouping_power,
+            max_value_power,
+            cutoff_power: 0,
+            lower_bin_count: cutoff_value as u32,
+            upper_bin_count: upper_bin_count as u32,
+            offset,
+            relative_accuracy: relative_accuracy.to_bits(),
+            ln_gamma: ln_gamma.to_bits(),
         })
     }
 
+    /// Returns the relative accuracy if this is a logarithmic configuration
+    /// created by [`Config::logarithmic`].
+    pub fn relative_accuracy(&self) -> Option<f64> {
+        (self.relative_accuracy != 0).then(|| f64::from_bits(self.relative_accuracy))
+    }
+
+    /// Returns the natural log of the ratio between consecutive bucket
+    /// boundaries if this is a logarithmic configuration.
+    fn ln_gamma(&self) -> Option<f64> {
+        (self.ln_gamma != 0).then(|| f64::from_bits(self.ln_gamma))
+    }
+
+    /// Returns the largest value which can be stored, which is the upper
+    /// bound of the last bucket.
+    const fn max(&self) -> u64 {
+        u64::MAX >> (64 - self.max_value_power)
+    }
+
     /// Returns the grouping power that was used to create this configuration.
+    ///
+    /// A logarithmic configuration does not subdivide powers of two, so this
+    /// does not describe its buckets. It is only the smallest grouping power
+    /// with at least the same precision, for comparison.
     pub const fn grouping_power(&self) -> u8 {
         self.grouping_power
     }
@@ -140,6 +368,13 @@ impl Config {
     /// a width of 1 and no error). For histograms with no logarithmic bins,
     /// error for the entire histogram is zero.
     pub fn error(&self) -> f64 {
+        if let Some(relative_accuracy) = self.relative_accuracy() {
+            return match self.upper_bin_count {
+                0 => 0.0,
+                _ => 100.0 * relative_accuracy,
+            };
+        }
+
         match self.grouping_power == self.max_value_power - 1 {
             true => 0.0,
             false => 100.0 / 2_u64.pow(self.grouping_power as u32) as f64,
@@ -154,23 +389,37 @@ impl Config {
     /// Converts a value to a bucket index. Returns an error if the value is
     /// outside of the range for the config.
     pub(crate) fn value_to_index(&self, value: u64) -> Result<usize, Error> {
-        if value < self.cutoff_value {
+        if value < self.lower_bin_count as u64 {
             return Ok(value as usize);
         }
 
-        if value > self.max {
+        if value > self.max() {
             return Err(Error::OutOfRange);
         }
 
+        if let Some(ln_gamma) = self.ln_gamma() {
+            let exponent = exponent(ln_gamma, value);
+            return Ok(self.lower_bin_count as usize + (exponent - self.offset) as usize);
+        }
+
         let power = 63 - value.leading_zeros();
         let log_bin = power - self.cutoff_power as u32;
         let offset = (value - (1 << power)) >> (power - self.grouping_power as u32);
 
-        Ok((self.lower_bin_count + log_bin * self.upper_bin_divisions + offset as u32) as usize)
+        Ok((self.lower_bin_count + (log_bin << self.grouping_power) + offset as u32) as usize)
     }
 
     /// Convert a bucket index to a lower bound.
     pub(crate) fn index_to_lower_bound(&self, index: usize) -> u64 {
+        if let Some(ln_gamma) = self.ln_gamma() {
+            if index <= self.lower_bin_count as usize {
+                return index as u64;
+            }
+
+            let exponent = self.offset + (index - self.lower_bin_count as usize) as i32;
+            return boundary(ln_gamma, exponent - 1) + 1;
+        }
+
         let g = index as u64 >> self.grouping_power;
         let h = index as u64 - g * (1 << self.grouping_power);
 
@@ -184,7 +433,16 @@ impl Config {
     /// Convert a bucket index to a upper inclusive bound.
     pub(crate) fn index_to_upper_bound(&self, index: usize) -> u64 {
         if index as u32 == self.lower_bin_count + self.upper_bin_count - 1 {
-            return self.max;
+            return self.max();
+        }
+
+        if let Some(ln_gamma) = self.ln_gamma() {
+            if index < self.lower_bin_count as usize {
+                return index as u64;
+            }
+
+            let exponent = self.offset + (index - self.lower_bin_count as usize) as i32;
+            return boundary(ln_gamma, exponent);
         }
         let g = index as u64 >> self.grouping_power;
         let h = index as u64 - g * (1 << self.grouping_power) + 1;
@@ -212,6 +470,31 @@ mod tests {
         assert_eq!(std::mem::size_of::<Config>(), 32);
     }
 
+    #[cfg(feature = "serde")]
+    #[test]
+    // Test that configurations written before logarithmic configurations were
+    // added can be read, and that every configuration round trips
+    fn serde() {
+        let baseline = r#"{"max":18446744073709551615,"grouping_power":7,"max_value_power":64,"cutoff_power":8,"cutoff_value":256,"lower_bin_count":256,"upper_bin_divisions":128,"upper_bin_count":7168}"#;
+        assert_eq!(
+            serde_json::from_str::<Config>(baseline).unwrap(),
+            Config::new(7, 64).unwrap()
+        );
+
+        for config in [
+            Config::new(7, 64).unwrap(),
+            Config::new(0, 1).unwrap(),
+            Config::logarithmic(0.01, 32).unwrap(),
+        ] {
+            let json = serde_json::to_string(&config).unwrap();
+            assert_eq!(serde_json::from_str::<Config>(&json).unwrap(), config);
+        }
+
+        // the bucket counts must match the parameters
+        let invalid = baseline.replace("7168", "7167");
+        assert!(serde_json::from_str::<Config>(&invalid).is_err());
+    }
+
     #[test]
     // Test that the number of buckets matches the expected count
     fn total_buckets() {
@@ -245,6 +528,10 @@ mod tests {
         assert_eq!(config.value_to_index(1032), Ok(513));
         assert_eq!(config.value_to_index(u64::MAX - 1), Ok(7423));
         assert_eq!(config.value_to_index(u64::MAX), Ok(7423));
+
+        let config = Config::new(2, 4).unwrap();
+        assert_eq!(config.value_to_index(15), Ok(11));
+        assert_eq!(config.value_to_index(16), Err(Error::OutOfRange));
     }
 
     #[test]
@@ -272,6 +559,9 @@ mod tests {
         assert_eq!(config.index_to_upper_bound(384), 515);
         assert_eq!(config.index_to_upper_bound(512), 1031);
         assert_eq!(config.index_to_upper_bound(7423), u64::MAX);
+
+        let config = Config::new(2, 4).unwrap();
+        assert_eq!(config.index_to_upper_bound(11), 15);
     }
 
     #[test]
@@ -288,4 +578,62 @@ mod tests {
             18_374_686_479_671_623_680..=u64::MAX
         );
     }
+
+    #[test]
+    // Test that logarithmic buckets are contiguous and within the accuracy
+    fn logarithmic() {
+        for (accuracy, max_value_power) in [(0.01, 64), (0.05, 32), (0.3, 10), (0.001, 20)] {
+            let config = Config::logarithmic(accuracy, max_value_power).unwrap();
+            assert_eq!(config.relative_accuracy(), Some(accuracy));
+            assert_eq!(config.error(), accuracy * 100.0);
+
+            let mut lower = 0;
+
+            for index in 0..config.total_buckets() {
+                let range = config.index_to_range(index);
+                assert_eq!(*range.start(), lower);
+                assert!(range.start() <= range.end());
+                assert_eq!(config.value_to_index(*range.start()), Ok(index));
+                assert_eq!(config.value_to_index(*range.end()), Ok(index));
+
+                let width = (range.end() - range.start()) as f64;
+                // allow for rounding in the floating point boundaries
+                assert!(
+                    width
+                        <= accuracy * (1.0 + 1e-9) * (*range.start() as f64 + *range.end() as f64)
+                );
+
+                lower = range.end().wrapping_add(1);
+            }
+
+            assert_eq!(lower, config.max().wrapping_add(1));
+        }
+
+        let config = Config::logarithmic(0.01, 16).unwrap();
+        assert_eq!(
+            config.value_to_index(u16::MAX as u64 + 1),
+            Err(Error::OutOfRange)
+        );
+        assert_eq!(Config::new(7, 16).unwrap().relative_accuracy(), None);
+
+        assert_eq!(
+            Config::logarithmic(0.0, 64),
+            Err(Error::IncompatibleParameters)
+        );
+        assert_eq!(
+            Config::logarithmic(1.0, 64),
+            Err(Error::IncompatibleParameters)
+        );
+        assert_eq!(
+            Config::logarithmic(f64::NAN, 64),
+            Err(Error::IncompatibleParameters)
+        );
+        // the exponent of the largest value does not fit in an i32
+        assert_eq!(
+            Config::logarithmic(1e-8, 64),
+            Err(Error::IncompatibleParameters)
+        );
+        assert_eq!(Config::logarithmic(0.01, 0), Err(Error::MaxPowerTooLow));
+        assert_eq!(Config::logarithmic(0.01, 65), Err(Error::MaxPowerTooHigh));
+    }
 }
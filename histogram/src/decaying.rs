@@ -0,0 +1,262 @@
+use crate::{Config, Error, Histogram};
+use clocksource::precise::{Duration, Instant};
+
+/// The number of half-lives the landmark may fall behind the newest
+/// observation before the weights are rescaled.
+const RESCALE_HALF_LIVES: f64 = 64.0;
+
+/// A histogram where the weight of each observation decays exponentially with
+/// its age, so that percentiles follow recent observations more closely than
+/// old ones.
+///
+/// Observations are weighted using forward decay: an observation recorded at
+/// instant `t` is given the weight `2^((t - landmark) / half_life)`, which
+/// grows for newer observations rather than shrinking the older ones. Since
+/// every weight would decay by the same factor, the percentiles do not depend
+/// on when they are read and recording never needs to touch other buckets. The
+/// weights are rescaled, and the landmark moved forward, when they would grow
+/// too large.
+///
+/// To use the percentile methods, the weights are converted to counts as of
+/// the newest instant the histogram has seen, in fixed point with
+/// [`DecayingHistogram::SCALE`] counts per observation. An observation
+/// recorded at that instant contributes `SCALE` to its bucket, one from a
+/// half-life earlier contributes half as much, and observations which have
+/// decayed to less than one count are ignored. See
+/// [`load`](DecayingHistogram::load).
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecayingHistogram {
+    config: Config,
+    half_life: Duration,
+    landmark: Instant,
+    latest: Instant,
+    weights: Box<[f64]>,
+}
+
+impl DecayingHistogram {
+    /// The count which an observation contributes to its bucket when it is
+    /// read at the instant it was recorded.
+    pub const SCALE: u64 = 1 << 16;
+
+    /// Construct a new decaying histogram from the provided parameters. See the
+    /// documentation for [`crate::Config`] to understand their meaning.
+    ///
+    /// The weight of each observation halves every `half_life`, which must be
+    /// non-zero.
+    pub fn new(
+        grouping_power: u8,
+        max_value_power: u8,
+        half_life: Duration,
+    ) -> Result<Self, Error> {
+        let config = Config::new(grouping_power, max_value_power)?;
+
+        Self::with_config(&config, half_life)
+    }
+
+    /// Creates a new decaying histogram using a provided [`crate::Config`]. The
+    /// landmark is the current instant.
+    pub fn with_config(config: &Config, half_life: Duration) -> Result<Self, Error> {
+        Self::with_start(config, half_life, Instant::now())
+    }
+
+    /// Creates a new decaying histogram using a provided [`crate::Config`] with
+    /// the landmark at the provided instant.
+    pub fn with_start(config: &Config, half_life: Duration, start: Instant) -> Result<Self, Error> {
+        if half_life.as_nanos() == 0 {
+            return Err(Error::IncompatibleParameters);
+        }
+
+        Ok(Self {
+            config: *config,
+            half_life,
+            landmark: start,
+            latest: start,
+            weights: vec![0.0; config.total_buckets()].into(),
+        })
+    }
+
+    /// Increment the bucket corresponding to the provided value by one
+    /// observation recorded at the provided instant.
+    pub fn increment(&mut self, time: Instant, value: u64) -> Result<(), Error> {
+        self.add(time, value, 1)
+    }
+
+    /// Add some count of observations recorded at the provided instant to the
+    /// bucket corresponding to the provided value.
+    ///
+    /// Instants may be out of order. Observations older than the newest
+    /// instant are given a correspondingly smaller weight.
+    pub fn add(&mut self, time: Instant, value: u64, count: u64) -> Result<(), Error> {
+        let index = self.config.value_to_index(value)?;
+
+        self.advance_to(time);
+        self.weights[index] += count as f64 * self.weight(time);
+
+        Ok(())
+    }
+
+    /// Moves the newest instant forward, which decays the counts that are read
+    /// from the histogram even if nothing has been recorded since. Instants
+    /// which are not newer have no effect.
+    pub fn advance_to(&mut self, time: Instant) {
+        if time <= self.latest {
+            return;
+        }
+
+        self.latest = time;
+
+        // rescale before the weight of new observations could overflow
+        let exponent = self.exponent(time);
+        if exponent > RESCALE_HALF_LIVES {
+            let factor = (-exponent).exp2();
+            for weight in self.weights.iter_mut() {
+                *weight *= factor;
+            }
+            self.landmark = time;
+        }
+    }
+
+    /// Returns the total decayed number of observations as of the newest
+    /// instant.
+    pub fn total_weight(&self) -> f64 {
+        self.weights.iter().sum::<f64>() * self.decay()
+    }
+
+    /// Returns a histogram holding the decayed counts as of the newest
+    /// instant, in units of [`DecayingHistogram::SCALE`] per observation,
+    /// rounded to the nearest count.
+    pub fn load(&self) -> Histogram {
+        let mut histogram = Histogram::with_config(&self.config);
+
+        for (bucket, (_, count)) in histogram.buckets.iter_mut().zip(self.counts()) {
+            *bucket = count;
+        }
+
+        histogram
+    }
+
+    /// Returns the bucket configuration of the histogram.
+    pub fn config(&self) -> Config {
+        self.config
+    }
+
+    /// Returns the duration over which the weight of an observation halves.
+    pub fn half_life(&self) -> Duration {
+        self.half_life
+    }
+
+    /// Returns the newest instant the histogram has seen.
+    pub fn latest(&self) -> Instant {
+        self.latest
+    }
+
+    /// Returns the decayed count of every bucket, including empty buckets, in
+    /// increasing order of index.
+    pub(crate) fn counts(&self) -> impl Iterator<Item = (usize, u64)> + Clone + '_ {
+        let factor = self.decay() * Self::SCALE as f64;
+
+        // the conversion saturates for weights which exceed a u64
+        self.weights
+            .iter()
+            .map(move |weight| (weight * factor).round() as u64)
+            .enumerate()
+    }
+
+    /// Returns the number of half-lives from the landmark to the instant,
+    /// which is negative for instants before the landmark.
+    fn exponent(&self, time: Instant) -> f64 {
+        let half_life = self.half_life.as_secs_f64();
+
+        match time.checked_duration_since(self.landmark) {
+            Some(elapsed) => elapsed.as_secs_f64() / half_life,
+            None => -(self.landmark - time).as_secs_f64() / half_life,
+        }
+    }
+
+    /// Returns the forward weight of an observation recorded at the instant.
+    fn weight(&self, time: Instant) -> f64 {
+        self.exponent(time).exp2()
+    }
+
+    /// Returns the factor which converts forward weights into weights as of
+    /// the newest instant.
+    fn decay(&self) -> f64 {
+        self.weight(self.latest).recip()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ReadHistogram;
+
+    #[test]
+    // Test that the weights halve every half-life
+    fn decay() {
+        let config = Config::new(7, 64).unwrap();
+        let start = Instant::now();
+        let mut histogram =
+            DecayingHistogram::with_start(&config, Duration::from_secs(10), start).unwrap();
+
+        histogram.add(start, 1, 4).unwrap();
+        assert_eq!(histogram.total_weight(), 4.0);
+
+        histogram.advance_to(start + Duration::from_secs(10));
+        assert!((histogram.total_weight() - 2.0).abs() < 1e-9);
+
+        histogram
+            .add(start + Duration::from_secs(20), 1000, 1)
+            .unwrap();
+        assert!((histogram.total_weight() - 2.0).abs() < 1e-9);
+        assert_eq!(
+            histogram.load().as_slice().iter().sum::<u64>(),
+            2 * DecayingHistogram::SCALE
+        );
+        assert_eq!(ReadHistogram::total_count(&histogram), 2);
+
+        // older instants are weighted as of their age
+        histogram.add(start, 1, 4).unwrap();
+        assert!((histogram.total_weight() - 3.0).abs() < 1e-9);
+        assert_eq!(histogram.latest(), start + Duration::from_secs(20));
+
+        assert_eq!(
+            DecayingHistogram::with_config(&config, Duration::from_nanos(0)),
+            Err(Error::IncompatibleParameters)
+        );
+    }
+
+    #[test]
+    // Test that percentiles shift towards recent observations and are kept
+    // across rescaling
+    fn percentiles() {
+        let config = Config::new(7, 64).unwrap();
+        let start = Instant::now();
+        let mut histogram =
+            DecayingHistogram::with_start(&config, Duration::from_secs(1), start).unwrap();
+
+        for _ in 0..100 {
+            histogram.increment(start, 10).unwrap();
+        }
+
+        let median = |histogram: &DecayingHistogram| histogram.load().percentile(50.0).unwrap();
+        assert_eq!(median(&histogram).map(|b| b.end()), Some(10));
+
+        // after one half-life, 100 new observations outweigh the old ones
+        let time = start + Duration::from_secs(1);
+        for _ in 0..100 {
+            histogram.increment(time, 1000).unwrap();
+        }
+        assert_eq!(median(&histogram).map(|b| b.end()), Some(1003));
+
+        // move far enough to rescale, after which the old values have decayed
+        // to nothing
+        let time = start + Duration::from_secs(100);
+        histogram.increment(time, 100).unwrap();
+        assert_eq!(median(&histogram).map(|b| b.end()), Some(100));
+        assert!((histogram.total_weight() - 1.0).abs() < 1e-9);
+        assert!(histogram.landmark > start);
+
+        histogram.increment(time, 10).unwrap();
+        assert_eq!(histogram.load().percentile(0.0).unwrap().unwrap().end(), 10);
+    }
+}
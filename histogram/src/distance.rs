@@ -0,0 +1,301 @@
+use crate::percentile::percentiles_into;
+use crate::{Bucket, Config, Error, ReadHistogram};
+use core::iter::Peekable;
+
+/// Returns the configuration in which both histograms can be compared. This is
+/// their configuration if they match, otherwise the standard configuration
+/// with the smaller grouping power, which the other can be downsampled to.
+fn common_config(a: &Config, b: &Config) -> Result<Config, Error> {
+    if a == b {
+        return Ok(*a);
+    }
+
+    if a.relative_accuracy().is_some()
+        || b.relative_accuracy().is_some()
+        || a.max_value_power() != b.max_value_power()
+    {
+        return Err(Error::IncompatibleParameters);
+    }
+
+    if a.grouping_power() < b.grouping_power() {
+        Ok(*a)
+    } else {
+        Ok(*b)
+    }
+}
+
+/// Maps the non-empty buckets of a histogram onto the buckets of a
+/// configuration with the same or a lower grouping power, merging the buckets
+/// which map to the same bucket.
+#[derive(Clone)]
+struct Rebucket<I: Iterator<Item = (usize, u64)>> {
+    from: Config,
+    to: Config,
+    counts: Peekable<I>,
+}
+
+impl<I: Iterator<Item = (usize, u64)>> Iterator for Rebucket<I> {
+    type Item = (usize, u64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let map = |index: usize| {
+            if self.from == self.to {
+                return index;
+            }
+
+            // every value in the source configuration is in range of the
+            // target, since they have the same max value power
+            self.to
+                .value_to_index(self.from.index_to_lower_bound(index))
+                .unwrap()
+        };
+
+        let (index, mut count) = self.counts.next()?;
+        let index = map(index);
+
+        while let Some((_, next)) = self.counts.next_if(|(next, _)| map(*next) == index) {
+            count = count.wrapping_add(next);
+        }
+
+        Some((index, count))
+    }
+}
+
+/// Returns the non-empty buckets of the histogram in the provided
+/// configuration.
+fn rebucket<'a>(
+    histogram: &'a (impl ReadHistogram + ?Sized),
+    config: &Config,
+) -> Rebucket<impl Iterator<Item = (usize, u64)> + Clone + 'a> {
+    Rebucket {
+        from: histogram.config(),
+        to: *config,
+        counts: histogram.counts().peekable(),
+    }
+}
+
+/// Walks the buckets which are non-empty in either histogram, in increasing
+/// order of index, and returns the configuration along with each bucket's
+/// index and the difference between the fractions of the observations of each
+/// histogram which are in it or below it.
+///
+/// Returns `None` if either histogram is empty.
+#[allow(clippy::type_complexity)]
+fn differences<'a>(
+    a: &'a (impl ReadHistogram + ?Sized),
+    b: &'a (impl ReadHistogram + ?Sized),
+) -> Result<Option<(Config, impl Iterator<Item = (usize, f64)> + 'a)>, Error> {
+    let config = common_config(&a.config(), &b.config())?;
+
+    let a = rebucket(a, &config);
+    let b = rebucket(b, &config);
+
+    let total_a: u128 = a.clone().map(|(_, count)| count as u128).sum();
+    let total_b: u128 = b.clone().map(|(_, count)| count as u128).sum();
+
+    if total_a == 0 || total_b == 0 {
+        return Ok(None);
+    }
+
+    let mut a = a.peekable();
+    let mut b = b.peekable();
+    let mut seen_a: u128 = 0;
+    let mut seen_b: u128 = 0;
+
+    let differences = core::iter::from_fn(move || {
+        let index = match (a.peek(), b.peek()) {
+            (Some((a, _)), Some((b, _))) => *a.min(b),
+            (Some((a, _)), None) => *a,
+            (None, Some((b, _))) => *b,
+            (None, None) => return None,
+        };
+
+        if let Some((_, count)) = a.next_if(|(i, _)| *i == index) {
+            seen_a += count as u128;
+        }
+        if let Some((_, count)) = b.next_if(|(i, _)| *i == index) {
+            seen_b += count as u128;
+        }
+
+        Some((
+            index,
+            seen_a as f64 / total_a as f64 - seen_b as f64 / total_b as f64,
+        ))
+    });
+
+    Ok(Some((config, differences)))
+}
+
+/// Returns the Kolmogorov-Smirnov statistic, which is the largest difference
+/// between the cumulative distributions of the histograms.
+pub(crate) fn kolmogorov_smirnov(
+    a: &(impl ReadHistogram + ?Sized),
+    b: &(impl ReadHistogram + ?Sized),
+) -> Result<Option<f64>, Error> {
+    Ok(differences(a, b)?.map(|(_, differences)| {
+        differences.fold(0.0, |max: f64, (_, difference)| max.max(difference.abs()))
+    }))
+}
+
+/// Returns the earth mover's distance between the histograms, treating each
+/// observation as the midpoint of its bucket.
+pub(crate) fn earth_movers_distance(
+    a: &(impl ReadHistogram + ?Sized),
+    b: &(impl ReadHistogram + ?Sized),
+) -> Result<Option<f64>, Error> {
+    let (config, differences) = match differences(a, b)? {
+        Some(differences) => differences,
+        None => return Ok(None),
+    };
+
+    let midpoint = |index: usize| {
+        config.index_to_lower_bound(index) as f64 / 2.0
+            + config.index_to_upper_bound(index) as f64 / 2.0
+    };
+
+    // in one dimension, the distance is the area between the cumulative
+    // distributions, which are constant between the non-empty buckets
+    let mut distance = 0.0;
+    let mut previous: Option<(usize, f64)> = None;
+
+    for (index, difference) in differences {
+        if let Some((previous, difference)) = previous {
+            distance += difference.abs() * (midpoint(index) - midpoint(previous));
+        }
+        previous = Some((index, difference));
+    }
+
+    Ok(Some(distance))
+}
+
+/// Returns the ratio of the midpoints of the buckets containing each
+/// percentile of the second histogram to those of the first, sorted by the
+/// percentile. There is no ratio where the midpoint in the first histogram is
+/// zero, which is only the case for the bucket holding zero.
+#[allow(clippy::type_complexity)]
+pub(crate) fn percentile_ratios(
+    a: &(impl ReadHistogram + ?Sized),
+    b: &(impl ReadHistogram + ?Sized),
+    percentiles: &[f64],
+) -> Result<Option<Vec<(f64, Option<f64>)>>, Error> {
+    let config = common_config(&a.config(), &b.config())?;
+
+    let mut a_buckets = Vec::with_capacity(percentiles.len());
+    let mut b_buckets = Vec::with_capacity(percentiles.len());
+
+    if !percentiles_into(&config, percentiles, rebucket(a, &config), &mut a_buckets)?
+        || !percentiles_into(&config, percentiles, rebucket(b, &config), &mut b_buckets)?
+    {
+        return Ok(None);
+    }
+
+    let midpoint = |bucket: &Bucket| bucket.start() as f64 / 2.0 + bucket.end() as f64 / 2.0;
+
+    Ok(Some(
+        a_buckets
+            .iter()
+            .zip(b_buckets.iter())
+            .map(|((percentile, a), (_, b))| {
+                let ratio = (midpoint(a) != 0.0).then(|| midpoint(b) / midpoint(a));
+                (*percentile, ratio)
+            })
+            .collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Config, Error, Histogram, ReadHistogram, SparseHistogram};
+
+    fn histogram(config: &Config, values: impl Iterator<Item = u64>) -> Histogram {
+        let mut histogram = Histogram::with_config(config);
+        for value in values {
+            histogram.increment(value).unwrap();
+        }
+        histogram
+    }
+
+    #[test]
+    // Test the distances between identical, disjoint, and shifted histograms
+    fn distances() {
+        let config = Config::new(7, 64).unwrap();
+        let a = histogram(&config, 0..100);
+        let b = histogram(&config, 100..200);
+        let c = histogram(&config, (0..100).chain(100..200));
+
+        assert_eq!(a.kolmogorov_smirnov(&a), Ok(Some(0.0)));
+        assert_eq!(a.kolmogorov_smirnov(&b), Ok(Some(1.0)));
+        assert_eq!(a.kolmogorov_smirnov(&c), Ok(Some(0.5)));
+        assert_eq!(c.kolmogorov_smirnov(&a), Ok(Some(0.5)));
+
+        // the distance is the difference between the means when one
+        // distribution is entirely above the other
+        let emd = |a: &Histogram, b: &Histogram| a.earth_movers_distance(b).unwrap().unwrap();
+        assert_eq!(emd(&a, &a), 0.0);
+        assert!((emd(&a, &b) - 100.0).abs() < 1e-9);
+        assert!((emd(&c, &a) - 50.0).abs() < 1e-9);
+
+        assert_eq!(
+            a.percentile_ratios(&b, &[100.0, 50.0]),
+            Ok(Some(vec![
+                (50.0, Some(149.0 / 49.0)),
+                (100.0, Some(199.0 / 99.0))
+            ]))
+        );
+
+        // there is no ratio to a percentile in the bucket holding zero
+        let zeros = histogram(&config, [0, 0, 0, 10].into_iter());
+        assert_eq!(
+            zeros.percentile_ratios(&a, &[0.0, 50.0, 100.0]),
+            Ok(Some(vec![
+                (0.0, None),
+                (50.0, None),
+                (100.0, Some(99.0 / 10.0))
+            ]))
+        );
+        assert_eq!(
+            a.percentile_ratios(&zeros, &[50.0]),
+            Ok(Some(vec![(50.0, Some(0.0))]))
+        );
+
+        let empty = Histogram::with_config(&config);
+        assert_eq!(a.kolmogorov_smirnov(&empty), Ok(None));
+        assert_eq!(empty.earth_movers_distance(&a), Ok(None));
+        assert_eq!(a.percentile_ratios(&empty, &[50.0]), Ok(None));
+        assert_eq!(
+            a.percentile_ratios(&b, &[101.0]),
+            Err(Error::InvalidPercentile)
+        );
+    }
+
+    #[test]
+    // Test that histograms are downsampled to a common configuration when
+    // possible
+    fn configs() {
+        let a = histogram(&Config::new(7, 64).unwrap(), 0..1000);
+        let b = SparseHistogram::from(&histogram(&Config::new(4, 64).unwrap(), 0..1000));
+
+        let downsampled = SparseHistogram::from(&a.downsample(4).unwrap());
+        assert_eq!(a.kolmogorov_smirnov(&b), Ok(Some(0.0)));
+        assert_eq!(b.earth_movers_distance(&a), Ok(Some(0.0)));
+        assert_eq!(
+            a.percentile_ratios(&b, &[50.0]),
+            downsampled.percentile_ratios(&b, &[50.0])
+        );
+
+        for config in [
+            Config::new(7, 32).unwrap(),
+            Config::logarithmic(0.01, 64).unwrap(),
+        ] {
+            let other = histogram(&config, 0..1000);
+            assert_eq!(
+                a.kolmogorov_smirnov(&other),
+                Err(Error::IncompatibleParameters)
+            );
+            assert_eq!(
+                other.earth_movers_distance(&a),
+                Err(Error::IncompatibleParameters)
+            );
+        }
+    }
+}
@@ -0,0 +1,252 @@
+//! A text report of the percentile distribution in the same layout as
+//! HdrHistogram's `outputPercentileDistribution`.
+//!
+//! There is one row for each non-empty bucket, with the value being the upper
+//! bound of the bucket. Since each row records the cumulative count, the
+//! report can be parsed back into an identical histogram. Each row ends with a
+//! bar which shows the count of the bucket relative to the largest bucket.
+
+use crate::{Config, Error, Statistics};
+use core::fmt::Write;
+
+/// The number of characters in the bar of the largest bucket.
+const BAR_WIDTH: u64 = 40;
+
+/// Writes the report for the non-empty buckets, which must be in increasing
+/// order of index. The maximum is the exact maximum, if known.
+pub(crate) fn write(
+    config: &Config,
+    buckets: impl Iterator<Item = (usize, u64)> + Clone,
+    statistics: &Statistics,
+    max: Option<u64>,
+) -> String {
+    let mut report = String::new();
+
+    let buckets = buckets.filter(|(_, count)| *count != 0);
+    let total = statistics.count();
+    let largest = buckets.clone().map(|(_, count)| count).max().unwrap_or(0);
+
+    // writing to a string cannot fail
+    let _ = writeln!(
+        report,
+        "{:>12} {:>14} {:>10} {:>14}\n",
+        "Value", "Percentile", "TotalCount", "1/(1-Percentile)"
+    );
+
+    let mut cumulative: u128 = 0;
+
+    for (index, count) in buckets {
+        cumulative += count as u128;
+
+        let percentile = cumulative as f64 / total as f64;
+        let inverse = if cumulative == total {
+            "inf".to_string()
+        } else {
+            format!("{:.2}", 1.0 / (1.0 - percentile))
+        };
+        let bar =
+            "#".repeat((count as u128 * BAR_WIDTH as u128).div_ceil(largest as u128) as usize);
+
+        let _ = writeln!(
+            report,
+            "{:>8}.000 {percentile:.12} {cumulative:>10} {inverse:>14} |{bar}",
+            config.index_to_upper_bound(index),
+        );
+    }
+
+    let max = max.or_else(|| statistics.max().map(|bucket| bucket.end()));
+
+    let _ = writeln!(
+        report,
+        "#[Mean    = {:>12.3}, StdDeviation   = {:>12.3}]",
+        statistics.mean().unwrap_or(0.0),
+        statistics.stddev().unwrap_or(0.0),
+    );
+    let _ = writeln!(
+        report,
+        "#[Max     = {:>8}.000, Total count    = {:>12}]",
+        max.unwrap_or(0),
+        total,
+    );
+    match config.relative_accuracy() {
+        None => {
+            let _ = writeln!(
+                report,
+                "#[Buckets = {:>12}, SubBuckets     = {:>12}]",
+                config.total_buckets(),
+                1_u64 << config.grouping_power(),
+            );
+        }
+        Some(accuracy) => {
+            let _ = writeln!(
+                report,
+                "#[Buckets = {:>12}, MaxValuePower  = {:>12}, RelativeAccuracy = {accuracy}]",
+                config.total_buckets(),
+                config.max_value_power(),
+            );
+        }
+    }
+
+    report
+}
+
+/// Parses a report into the configuration and the non-empty buckets, in
+/// increasing order of index.
+///
+/// Only reports written by this crate are supported. HdrHistogram writes a
+/// footer with the same layout, but its bucket and sub-bucket counts describe
+/// a different bucketing, so its reports cannot be parsed. Rows may repeat a
+/// bucket, in which case the counts are combined.
+pub(crate) fn parse(report: &str) -> Result<(Config, Vec<(usize, u64)>), Error> {
+    let config = parse_config(report)?;
+
+    let mut buckets: Vec<(usize, u64)> = Vec::new();
+    let mut previous: u128 = 0;
+
+    for line in report.lines().skip(1) {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut columns = line.split_whitespace();
+
+        let value = columns
+            .next()
+            .and_then(|value| value.split('.').next())
+            .and_then(|value| value.parse::<u64>().ok())
+            .ok_or(Error::InvalidEncoding)?;
+        let cumulative = columns
+            .nth(1)
+            .and_then(|total| total.parse::<u128>().ok())
+            .ok_or(Error::InvalidEncoding)?;
+
+        let count = cumulative
+            .checked_sub(previous)
+            .and_then(|count| u64::try_from(count).ok())
+            .ok_or(Error::InvalidEncoding)?;
+        previous = cumulative;
+
+        if count == 0 {
+            continue;
+        }
+
+        let index = config
+            .value_to_index(value)
+            .map_err(|_| Error::InvalidEncoding)?;
+
+        match buckets.last_mut() {
+            Some((last, total)) if *last == index => {
+                *total = total.checked_add(count).ok_or(Error::InvalidEncoding)?;
+            }
+            Some((last, _)) if *last > index => return Err(Error::InvalidEncoding),
+            _ => buckets.push((index, count)),
+        }
+    }
+
+    Ok((config, buckets))
+}
+
+/// Recovers the configuration from the number of buckets and sub-buckets in
+/// the footer of the report, which have the meaning used by this crate rather
+/// than by HdrHistogram.
+fn parse_config(report: &str) -> Result<Config, Error> {
+    let footer = report
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("#[Buckets"))
+        .ok_or(Error::InvalidEncoding)?;
+
+    if footer.contains("RelativeAccuracy") {
+        return parse_logarithmic_config(footer);
+    }
+
+    let mut fields = footer
+        .split(['=', ',', ']'])
+        .filter_map(|field| field.trim().parse::<u64>().ok());
+
+    let (total, sub_buckets) = match (fields.next(), fields.next()) {
+        (Some(total), Some(sub_buckets)) if sub_buckets.is_power_of_two() => (total, sub_buckets),
+        _ => return Err(Error::InvalidEncoding),
+    };
+
+    // the total is `2^(g + 1)` linear buckets plus `2^g` buckets for each
+    // power of two between `2^(g + 1)` and `2^n`
+    let grouping_power = sub_buckets.trailing_zeros() as u64;
+    let logarithmic = total
+        .checked_sub(sub_buckets * 2)
+        .filter(|buckets| buckets.is_multiple_of(sub_buckets))
+        .ok_or(Error::InvalidEncoding)?;
+    let max_value_power = grouping_power + 1 + logarithmic / sub_buckets;
+
+    let config = u8::try_from(max_value_power)
+        .ok()
+        .and_then(|max_value_power| Config::new(grouping_power as u8, max_value_power).ok())
+        .ok_or(Error::InvalidEncoding)?;
+
+    if config.total_buckets() as u64 != total {
+        return Err(Error::InvalidEncoding);
+    }
+
+    Ok(config)
+}
+
+/// Recovers a logarithmic configuration from the footer of the report, which
+/// includes the max value power and relative accuracy.
+fn parse_logarithmic_config(footer: &str) -> Result<Config, Error> {
+    let mut fields = footer
+        .split([',', ']'])
+        .filter_map(|field| field.split_once('='))
+        .map(|(_, value)| value.trim());
+
+    let (total, max_value_power, accuracy) = match (fields.next(), fields.next(), fields.next()) {
+        (Some(total), Some(max_value_power), Some(accuracy)) => (
+            total.parse::<usize>().ok(),
+            max_value_power.parse::<u8>().ok(),
+            accuracy.parse::<f64>().ok(),
+        ),
+        _ => return Err(Error::InvalidEncoding),
+    };
+
+    let config = match (max_value_power, accuracy) {
+        (Some(max_value_power), Some(accuracy)) => {
+            Config::logarithmic(accuracy, max_value_power).map_err(|_| Error::InvalidEncoding)?
+        }
+        _ => return Err(Error::InvalidEncoding),
+    };
+
+    if total != Some(config.total_buckets()) {
+        return Err(Error::InvalidEncoding);
+    }
+
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    // Tests that the configuration is recovered from the footer
+    fn config() {
+        let configs = [(0, 1), (2, 4), (7, 64), (14, 64), (3, 20)]
+            .iter()
+            .map(|(g, n)| Config::new(*g, *n).unwrap())
+            .chain([Config::logarithmic(0.01, 64).unwrap()]);
+
+        for config in configs {
+            let report = write(
+                &config,
+                [].into_iter(),
+                &Statistics::from_buckets(&config, [].into_iter()),
+                None,
+            );
+            assert_eq!(parse(&report), Ok((config, vec![])));
+        }
+
+        assert_eq!(parse(""), Err(Error::InvalidEncoding));
+        assert_eq!(
+            parse("#[Buckets = 13, SubBuckets = 4]"),
+            Err(Error::InvalidEncoding)
+        );
+    }
+}
@@ -0,0 +1,372 @@
+use crate::{Config, Error, Histogram, SparseHistogram};
+
+// The encoding is shared between `Histogram` and `SparseHistogram`, so data
+// encoded from either type may be decoded into the other. See the docs for
+// `Histogram::to_bytes` for a description of the format.
+
+/// The current version of the binary encoding.
+const VERSION: u8 = 1;
+
+/// The version of the binary encoding for logarithmic configurations, which
+/// differs only in the header.
+const LOGARITHMIC_VERSION: u8 = 2;
+
+/// The maximum number of bytes in the configuration header.
+pub(crate) const MAX_CONFIG_LEN: usize = 10;
+
+/// The maximum number of bytes in a LEB128 encoded `u64`.
+const MAX_VARINT_LEN: usize = 10;
+
+impl Histogram {
+    /// Encode this histogram into a compact, versioned binary format. Only the
+    /// non-zero buckets are written:
+    ///
+    /// | field             | encoding                                |
+    /// |-------------------|-----------------------------------------|
+    /// | version           | `u8`, currently `1`                     |
+    /// | `grouping_power`  | `u8`                                    |
+    /// | `max_value_power` | `u8`                                    |
+    /// | bucket count      | varint                                  |
+    /// | bucket index      | varint, delta from the previous index   |
+    /// | bucket count      | varint, must be non-zero                |
+    ///
+    /// Varints are unsigned LEB128. The first index is written as a delta from
+    /// zero and every following index must be strictly greater than the
+    /// previous one. The encoding is the same as for
+    /// [`crate::SparseHistogram::to_bytes`].
+    ///
+    /// Histograms with a [`Config::logarithmic`] configuration use version
+    /// `2`, where the `grouping_power` and `max_value_power` are replaced by
+    /// the `max_value_power` and the relative accuracy as a little-endian
+    /// `f64`.
+    ///
+    /// Only the buckets are encoded. The exact values of a histogram created
+    /// with [`Histogram::with_exact`] are not, so the decoded histogram does
+    /// not track them and is not equal to the original.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let buckets = self
+            .as_slice()
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count != 0)
+            .map(|(index, count)| (index, *count));
+
+        encode(&self.config, buckets)
+    }
+
+    /// Decode a histogram from the compact binary format produced by
+    /// [`to_bytes`](Histogram::to_bytes).
+    ///
+    /// An error is returned if the encoding is malformed or describes buckets
+    /// which are not valid for the encoded configuration. The decoded
+    /// histogram never tracks exact values.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        SparseHistogram::from_bytes(bytes).map(|histogram| Histogram::from(&histogram))
+    }
+}
+
+impl SparseHistogram {
+    /// Encode this histogram into a compact, versioned binary format. See
+    /// [`crate::Histogram::to_bytes`] for a description of the format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let buckets = self
+            .index
+            .iter()
+            .zip(self.count.iter())
+            .filter(|(_, count)| **count != 0)
+            .map(|(index, count)| (*index, *count));
+
+        encode(&self.config, buckets)
+    }
+
+    /// Decode a histogram from the compact binary format produced by
+    /// [`to_bytes`](SparseHistogram::to_bytes).
+    ///
+    /// An error is returned if the encoding is malformed or describes buckets
+    /// which are not valid for the encoded configuration.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut bytes = bytes;
+        let config = read_config(&mut bytes)?;
+
+        let total_buckets = config.total_buckets() as u64;
+
+        let len = read_varint(&mut bytes)?;
+
+        // each bucket requires at least two bytes
+        if len > total_buckets || len > (bytes.len() / 2) as u64 {
+            return Err(Error::InvalidEncoding);
+        }
+
+        let mut histogram = SparseHistogram::with_config(&config);
+        histogram.index.reserve(len as usize);
+        histogram.count.reserve(len as usize);
+
+        let mut previous: Option<u64> = None;
+
+        for _ in 0..len {
+            let delta = read_varint(&mut bytes)?;
+            let count = read_varint(&mut bytes)?;
+
+            let index = match previous {
+                None => delta,
+                Some(_) if delta == 0 => return Err(Error::InvalidEncoding),
+                Some(previous) => previous.checked_add(delta).ok_or(Error::InvalidEncoding)?,
+            };
+
+            if index >= total_buckets || count == 0 {
+                return Err(Error::InvalidEncoding);
+            }
+
+            histogram.index.push(index as usize);
+            histogram.count.push(count);
+            previous = Some(index);
+        }
+
+        if !bytes.is_empty() {
+            return Err(Error::InvalidEncoding);
+        }
+
+        Ok(histogram)
+    }
+}
+
+/// Encodes the configuration and the non-zero buckets, which must be provided
+/// in increasing order of index.
+fn encode(config: &Config, buckets: impl Iterator<Item = (usize, u64)>) -> Vec<u8> {
+    let buckets: Vec<(usize, u64)> = buckets.collect();
+
+    let mut bytes = Vec::with_capacity(MAX_CONFIG_LEN + MAX_VARINT_LEN * (1 + 2 * buckets.len()));
+
+    write_config(&mut bytes, config);
+    write_varint(&mut bytes, buckets.len() as u64);
+
+    let mut previous = 0;
+
+    for (index, count) in buckets {
+        write_varint(&mut bytes, (index - previous) as u64);
+        write_varint(&mut bytes, count);
+        previous = index;
+    }
+
+    bytes
+}
+
+/// Appends the versioned configuration header to the buffer, which is at most
+/// `MAX_CONFIG_LEN` bytes.
+pub(crate) fn write_config(bytes: &mut Vec<u8>, config: &Config) {
+    match config.relative_accuracy() {
+        None => {
+            bytes.push(VERSION);
+            bytes.push(config.grouping_power());
+            bytes.push(config.max_value_power());
+        }
+        Some(accuracy) => {
+            bytes.push(LOGARITHMIC_VERSION);
+            bytes.push(config.max_value_power());
+            bytes.extend_from_slice(&accuracy.to_le_bytes());
+        }
+    }
+}
+
+/// Reads the versioned configuration header from the front of the buffer and
+/// advances the buffer past it.
+pub(crate) fn read_config(bytes: &mut &[u8]) -> Result<Config, Error> {
+    let (version, rest) = bytes.split_first().ok_or(Error::InvalidEncoding)?;
+
+    match *version {
+        VERSION => {
+            let (header, rest) = rest
+                .split_first_chunk::<2>()
+                .ok_or(Error::InvalidEncoding)?;
+            *bytes = rest;
+
+            let [grouping_power, max_value_power] = *header;
+            Config::new(grouping_power, max_value_power)
+        }
+        LOGARITHMIC_VERSION => {
+            let (header, rest) = rest
+                .split_first_chunk::<9>()
+                .ok_or(Error::InvalidEncoding)?;
+            *bytes = rest;
+
+            let (max_value_power, accuracy) = header.split_first().unwrap();
+            let accuracy = f64::from_le_bytes(accuracy.try_into().unwrap());
+            Config::logarithmic(accuracy, *max_value_power)
+        }
+        _ => Err(Error::UnsupportedVersion),
+    }
+}
+
+/// Appends a value to the buffer as an unsigned LEB128 varint.
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+/// Reads an unsigned LEB128 varint from the front of the buffer and advances
+/// the buffer past it.
+fn read_varint(bytes: &mut &[u8]) -> Result<u64, Error> {
+    let mut value: u64 = 0;
+
+    for (i, byte) in bytes.iter().take(MAX_VARINT_LEN).enumerate() {
+        let bits = (*byte & 0x7f) as u64;
+        let shift = 7 * i as u32;
+
+        // reject bits which would be shifted beyond 64 bits
+        if shift == 63 && bits > 1 {
+            return Err(Error::InvalidEncoding);
+        }
+
+        value |= bits << shift;
+
+        if *byte & 0x80 == 0 {
+            *bytes = &bytes[(i + 1)..];
+            return Ok(value);
+        }
+    }
+
+    Err(Error::InvalidEncoding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    // Test varint round trips at the boundaries
+    fn varint() {
+        for value in [0, 1, 127, 128, 16_383, 16_384, u32::MAX as u64, u64::MAX] {
+            let mut bytes = Vec::new();
+            write_varint(&mut bytes, value);
+            let mut slice = bytes.as_slice();
+            assert_eq!(read_varint(&mut slice), Ok(value));
+            assert!(slice.is_empty());
+        }
+
+        assert_eq!(
+            read_varint(&mut [0x80].as_slice()),
+            Err(Error::InvalidEncoding)
+        );
+        assert_eq!(
+            read_varint(&mut [0xff; 10].as_slice()),
+            Err(Error::InvalidEncoding)
+        );
+    }
+
+    #[test]
+    // Test round trips between the histogram types
+    fn round_trip() {
+        let mut histogram = Histogram::new(7, 64).unwrap();
+
+        assert_eq!(histogram.to_bytes(), vec![1, 7, 64, 0]);
+        assert_eq!(
+            Histogram::from_bytes(&histogram.to_bytes()),
+            Ok(histogram.clone())
+        );
+
+        for v in [0, 1, 100, 1024, 1_000_000, u64::MAX] {
+            histogram.add(v, v / 2 + 1).unwrap();
+        }
+
+        let sparse = SparseHistogram::from(&histogram);
+        let bytes = histogram.to_bytes();
+
+        assert_eq!(sparse.to_bytes(), bytes);
+        assert_eq!(Histogram::from_bytes(&bytes), Ok(histogram.clone()));
+        assert_eq!(SparseHistogram::from_bytes(&bytes), Ok(sparse));
+
+        // exact values are not encoded
+        let mut exact = Histogram::with_exact(&histogram.config());
+        for v in [0, 1, 100, 1024, 1_000_000, u64::MAX] {
+            exact.add(v, v / 2 + 1).unwrap();
+        }
+        assert_eq!(exact.to_bytes(), bytes);
+
+        let decoded = Histogram::from_bytes(&exact.to_bytes()).unwrap();
+        assert_eq!(decoded.exact(), None);
+        assert_eq!(decoded.as_slice(), exact.as_slice());
+        assert_ne!(decoded, exact);
+    }
+
+    #[test]
+    // Test that logarithmic configurations round trip
+    fn logarithmic() {
+        let config = Config::logarithmic(0.02, 48).unwrap();
+        let mut histogram = Histogram::with_config(&config);
+
+        for v in [0, 1, 100, 1024, 1_000_000, (1 << 48) - 1] {
+            histogram.add(v, v / 2 + 1).unwrap();
+        }
+
+        let bytes = histogram.to_bytes();
+        assert_eq!(&bytes[..2], &[2, 48]);
+        assert_eq!(Histogram::from_bytes(&bytes), Ok(histogram.clone()));
+        assert_eq!(
+            SparseHistogram::from_bytes(&bytes),
+            Ok(SparseHistogram::from(&histogram))
+        );
+
+        // invalid accuracy
+        let mut bytes = vec![2, 48];
+        bytes.extend_from_slice(&1.5_f64.to_le_bytes());
+        bytes.push(0);
+        assert_eq!(
+            Histogram::from_bytes(&bytes),
+            Err(Error::IncompatibleParameters)
+        );
+    }
+
+    #[test]
+    // Test that malformed encodings are rejected
+    fn validation() {
+        assert_eq!(Histogram::from_bytes(&[]), Err(Error::InvalidEncoding));
+        assert_eq!(Histogram::from_bytes(&[1, 7]), Err(Error::InvalidEncoding));
+        assert_eq!(
+            Histogram::from_bytes(&[3, 7, 64, 0]),
+            Err(Error::UnsupportedVersion)
+        );
+        assert_eq!(
+            Histogram::from_bytes(&[1, 7, 65, 0]),
+            Err(Error::MaxPowerTooHigh)
+        );
+
+        // trailing bytes
+        assert_eq!(
+            Histogram::from_bytes(&[1, 7, 64, 0, 0]),
+            Err(Error::InvalidEncoding)
+        );
+
+        // more buckets than the config allows
+        assert_eq!(
+            SparseHistogram::from_bytes(&[1, 1, 2, 5, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1]),
+            Err(Error::InvalidEncoding)
+        );
+
+        // index beyond the total buckets
+        assert_eq!(
+            SparseHistogram::from_bytes(&[1, 1, 2, 1, 4, 1]),
+            Err(Error::InvalidEncoding)
+        );
+
+        // repeated index
+        assert_eq!(
+            SparseHistogram::from_bytes(&[1, 1, 2, 2, 1, 1, 0, 1]),
+            Err(Error::InvalidEncoding)
+        );
+
+        // zero count
+        assert_eq!(
+            SparseHistogram::from_bytes(&[1, 1, 2, 1, 1, 0]),
+            Err(Error::InvalidEncoding)
+        );
+
+        // truncated
+        assert_eq!(
+            SparseHistogram::from_bytes(&[1, 1, 2, 2, 1, 1]),
+            Err(Error::InvalidEncoding)
+        );
+    }
+}
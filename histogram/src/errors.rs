@@ -22,4 +22,8 @@ pub enum Error {
     Underflow,
     #[error("the histogram is not a subset")]
     InvalidSubset,
+    #[error("the encoded histogram is invalid")]
+    InvalidEncoding,
+    #[error("the encoding version is not supported")]
+    UnsupportedVersion,
 }
@@ -0,0 +1,231 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// The exact sum, minimum, and maximum of the values recorded into a
+/// histogram.
+///
+/// Buckets only record an approximate value for each observation, so
+/// statistics derived from them carry the error of the bucket widths. When
+/// exact tracking is enabled, for example with
+/// [`AtomicHistogram::with_exact`](crate::AtomicHistogram::with_exact), these
+/// values are maintained alongside the buckets.
+///
+/// The minimum and maximum are not known if the histogram is empty, or if they
+/// cannot be determined after subtracting one histogram from another.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Exact {
+    pub(crate) sum: u128,
+    pub(crate) min: Option<u64>,
+    pub(crate) max: Option<u64>,
+}
+
+impl Exact {
+    /// Returns the exact sum of all recorded values.
+    pub fn sum(&self) -> u128 {
+        self.sum
+    }
+
+    /// Returns the smallest recorded value, if known.
+    pub fn min(&self) -> Option<u64> {
+        self.min
+    }
+
+    /// Returns the largest recorded value, if known.
+    pub fn max(&self) -> Option<u64> {
+        self.max
+    }
+
+    /// Record some count of observations of the value.
+    pub(crate) fn add(&mut self, value: u64, count: u64) {
+        if count == 0 {
+            return;
+        }
+
+        self.sum = self.sum.wrapping_add(value as u128 * count as u128);
+        self.min = Some(self.min.map_or(value, |min| min.min(value)));
+        self.max = Some(self.max.map_or(value, |max| max.max(value)));
+    }
+
+    /// Combines the extremes of two sets of observations.
+    fn merge(&self, other: &Exact, sum: u128) -> Exact {
+        let min = match (self.min, other.min) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        let max = match (self.max, other.max) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        };
+
+        Exact { sum, min, max }
+    }
+
+    /// Adds the other observations, returning `None` on overflow.
+    pub(crate) fn checked_add(&self, other: &Exact) -> Option<Exact> {
+        self.sum
+            .checked_add(other.sum)
+            .map(|sum| self.merge(other, sum))
+    }
+
+    /// Adds the other observations, allowing the sum to wrap.
+    pub(crate) fn wrapping_add(&self, other: &Exact) -> Exact {
+        self.merge(other, self.sum.wrapping_add(other.sum))
+    }
+
+    /// Subtracts the other observations, returning `None` on underflow. The
+    /// extremes are only kept if nothing was removed.
+    pub(crate) fn checked_sub(&self, other: &Exact) -> Option<Exact> {
+        self.sum
+            .checked_sub(other.sum)
+            .map(|sum| self.remove(other, sum))
+    }
+
+    /// Subtracts the other observations, allowing the sum to wrap. The extremes
+    /// are only kept if nothing was removed.
+    pub(crate) fn wrapping_sub(&self, other: &Exact) -> Exact {
+        self.remove(other, self.sum.wrapping_sub(other.sum))
+    }
+
+    fn remove(&self, other: &Exact, sum: u128) -> Exact {
+        if other.min.is_none() {
+            Exact { sum, ..*self }
+        } else {
+            Exact {
+                sum,
+                min: None,
+                max: None,
+            }
+        }
+    }
+}
+
+/// The atomic counterpart to [`Exact`], used by the atomic histogram.
+///
+/// The sum is held across two words, so a concurrent reader may observe the
+/// low word wrap before the carry is applied to the high word. As with the
+/// bucket counters, the values are only guaranteed to be consistent once
+/// writers have finished.
+#[derive(Debug)]
+pub(crate) struct AtomicExact {
+    sum_low: AtomicU64,
+    sum_high: AtomicU64,
+    min: AtomicU64,
+    max: AtomicU64,
+}
+
+impl AtomicExact {
+    pub(crate) fn new() -> Self {
+        Self {
+            sum_low: AtomicU64::new(0),
+            sum_high: AtomicU64::new(0),
+            min: AtomicU64::new(u64::MAX),
+            max: AtomicU64::new(0),
+        }
+    }
+
+    /// Record some count of observations of the value.
+    pub(crate) fn add(&self, value: u64, count: u64) {
+        if count == 0 {
+            return;
+        }
+
+        let sum = value as u128 * count as u128;
+        let low = sum as u64;
+        let high = (sum >> 64) as u64;
+
+        let previous = self.sum_low.fetch_add(low, Ordering::Relaxed);
+        let carry = previous.overflowing_add(low).1 as u64;
+
+        if high + carry != 0 {
+            self.sum_high.fetch_add(high + carry, Ordering::Relaxed);
+        }
+
+        self.min.fetch_min(value, Ordering::Relaxed);
+        self.max.fetch_max(value, Ordering::Relaxed);
+    }
+
+    /// Read the current values.
+    pub(crate) fn load(&self) -> Exact {
+        Self::exact(
+            self.sum_low.load(Ordering::Relaxed),
+            self.sum_high.load(Ordering::Relaxed),
+            self.min.load(Ordering::Relaxed),
+            self.max.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Read the current values and reset them.
+    #[cfg(target_has_atomic = "64")]
+    pub(crate) fn drain(&self) -> Exact {
+        Self::exact(
+            self.sum_low.swap(0, Ordering::Relaxed),
+            self.sum_high.swap(0, Ordering::Relaxed),
+            self.min.swap(u64::MAX, Ordering::Relaxed),
+            self.max.swap(0, Ordering::Relaxed),
+        )
+    }
+
+    fn exact(low: u64, high: u64, min: u64, max: u64) -> Exact {
+        // nothing has been recorded while the minimum is above the maximum
+        let (min, max) = if min > max {
+            (None, None)
+        } else {
+            (Some(min), Some(max))
+        };
+
+        Exact {
+            sum: ((high as u128) << 64) | low as u128,
+            min,
+            max,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    // Test that the atomic sum carries into the high word
+    fn atomic() {
+        let exact = AtomicExact::new();
+        assert_eq!(exact.load(), Exact::default());
+
+        exact.add(u64::MAX, 3);
+        exact.add(7, 1);
+        exact.add(5, 0);
+
+        let mut expected = Exact::default();
+        expected.add(u64::MAX, 3);
+        expected.add(7, 1);
+
+        assert_eq!(exact.load(), expected);
+        assert_eq!(expected.sum(), u64::MAX as u128 * 3 + 7);
+        assert_eq!(expected.min(), Some(7));
+        assert_eq!(expected.max(), Some(u64::MAX));
+
+        assert_eq!(exact.drain(), expected);
+        assert_eq!(exact.load(), Exact::default());
+    }
+
+    #[test]
+    // Test which values survive arithmetic
+    fn arithmetic() {
+        let mut a = Exact::default();
+        a.add(10, 2);
+        let mut b = Exact::default();
+        b.add(3, 1);
+
+        let sum = a.checked_add(&b).unwrap();
+        assert_eq!(sum.sum(), 23);
+        assert_eq!(sum.min(), Some(3));
+        assert_eq!(sum.max(), Some(10));
+
+        let difference = sum.checked_sub(&b).unwrap();
+        assert_eq!(difference.sum(), 20);
+        assert_eq!(difference.min(), None);
+
+        assert_eq!(sum.checked_sub(&Exact::default()), Some(sum));
+        assert_eq!(b.checked_sub(&a), None);
+    }
+}
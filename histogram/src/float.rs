@@ -0,0 +1,192 @@
+use crate::{Config, Error, Histogram, Interpolation};
+use core::ops::RangeInclusive;
+
+/// A histogram which records non-negative floating point values, such as
+/// ratios.
+///
+/// Each value is multiplied by a scale factor and rounded to the nearest
+/// integer before being recorded using the bucketing scheme of the provided
+/// [`crate::Config`]. The scale therefore sets the finest resolution, which is
+/// `1 / scale`, while the `max_value_power` bounds the largest scaled value.
+/// Percentiles are reported in the original, unscaled domain.
+///
+/// For example, a scale of `1000.0` records values with a resolution of
+/// `0.001` and with a `max_value_power` of `20`, values up to about `1048.5`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FloatHistogram {
+    scale: f64,
+    histogram: Histogram,
+}
+
+impl FloatHistogram {
+    /// Construct a new histogram from the provided parameters. See the
+    /// documentation for [`crate::Config`] to understand their meaning.
+    pub fn new(grouping_power: u8, max_value_power: u8, scale: f64) -> Result<Self, Error> {
+        let config = Config::new(grouping_power, max_value_power)?;
+
+        Self::with_config(&config, scale)
+    }
+
+    /// Creates a new histogram using a provided [`crate::Config`] and scale
+    /// factor.
+    ///
+    /// An error is returned if the scale is not a positive, finite number.
+    pub fn with_config(config: &Config, scale: f64) -> Result<Self, Error> {
+        if !scale.is_finite() || scale <= 0.0 {
+            return Err(Error::IncompatibleParameters);
+        }
+
+        Ok(Self {
+            scale,
+            histogram: Histogram::with_config(config),
+        })
+    }
+
+    /// Increment the counter for the bucket corresponding to the provided value
+    /// by one.
+    pub fn increment(&mut self, value: f64) -> Result<(), Error> {
+        self.add(value, 1)
+    }
+
+    /// Add some count to the counter for the bucket corresponding to the
+    /// provided value.
+    ///
+    /// An error is returned if the value is negative, not finite, or too large
+    /// for the configuration once scaled.
+    pub fn add(&mut self, value: f64, count: u64) -> Result<(), Error> {
+        if value.is_nan() || value < 0.0 {
+            return Err(Error::OutOfRange);
+        }
+
+        let scaled = (value * self.scale).round();
+
+        if scaled >= u64::MAX as f64 {
+            return Err(Error::OutOfRange);
+        }
+
+        self.histogram.add(scaled as u64, count)
+    }
+
+    /// Returns the scale factor applied to values before they are recorded.
+    pub fn scale(&self) -> f64 {
+        self.scale
+    }
+
+    /// Returns the underlying histogram of scaled values.
+    pub fn histogram(&self) -> &Histogram {
+        &self.histogram
+    }
+
+    /// Returns the bucket configuration of the histogram.
+    pub fn config(&self) -> Config {
+        self.histogram.config()
+    }
+
+    /// Return a collection of percentiles from this histogram as the range of
+    /// unscaled values in the bucket which contains each percentile.
+    ///
+    /// Each percentile should be in the inclusive range `0.0..=100.0`. The
+    /// results will be sorted by the percentile.
+    #[allow(clippy::type_complexity)]
+    pub fn percentiles(
+        &self,
+        percentiles: &[f64],
+    ) -> Result<Option<Vec<(f64, RangeInclusive<f64>)>>, Error> {
+        Ok(self.histogram.percentiles(percentiles)?.map(|buckets| {
+            buckets
+                .iter()
+                .map(|(percentile, bucket)| {
+                    let start = bucket.start() as f64 / self.scale;
+                    let end = bucket.end() as f64 / self.scale;
+                    (*percentile, start..=end)
+                })
+                .collect()
+        }))
+    }
+
+    /// Return a single percentile from this histogram as the range of unscaled
+    /// values in the bucket which contains it.
+    ///
+    /// The percentile should be in the inclusive range `0.0..=100.0`.
+    pub fn percentile(&self, percentile: f64) -> Result<Option<RangeInclusive<f64>>, Error> {
+        self.percentiles(&[percentile])
+            .map(|v| v.map(|x| x[0].1.clone()))
+    }
+
+    /// Return a collection of estimated unscaled values for the percentiles
+    /// from this histogram, using the provided [`crate::Interpolation`]
+    /// strategy.
+    ///
+    /// Each percentile should be in the inclusive range `0.0..=100.0`. The
+    /// results will be sorted by the percentile.
+    pub fn percentile_values(
+        &self,
+        percentiles: &[f64],
+        interpolation: Interpolation,
+    ) -> Result<Option<Vec<(f64, f64)>>, Error> {
+        Ok(self
+            .histogram
+            .percentile_values(percentiles, interpolation)?
+            .map(|values| {
+                values
+                    .iter()
+                    .map(|(percentile, value)| (*percentile, value / self.scale))
+                    .collect()
+            }))
+    }
+
+    /// Return an estimated unscaled value for a single percentile from this
+    /// histogram, using the provided [`crate::Interpolation`] strategy.
+    ///
+    /// The percentile should be in the inclusive range `0.0..=100.0`.
+    pub fn percentile_value(
+        &self,
+        percentile: f64,
+        interpolation: Interpolation,
+    ) -> Result<Option<f64>, Error> {
+        self.percentile_values(&[percentile], interpolation)
+            .map(|v| v.map(|x| x[0].1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    // Tests that the scale is validated
+    fn scale() {
+        let config = Config::new(7, 32).unwrap();
+        for scale in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert_eq!(
+                FloatHistogram::with_config(&config, scale),
+                Err(Error::IncompatibleParameters)
+            );
+        }
+    }
+
+    #[test]
+    // Tests percentiles in the unscaled domain
+    fn percentiles() {
+        let mut histogram = FloatHistogram::new(7, 32, 100.0).unwrap();
+        assert_eq!(histogram.percentile(50.0), Ok(None));
+
+        for v in 0..100 {
+            histogram.increment(v as f64 / 100.0).unwrap();
+        }
+
+        assert_eq!(histogram.percentile(50.0), Ok(Some(0.49..=0.49)));
+        assert_eq!(
+            histogram.percentile_value(100.0, Interpolation::Upper),
+            Ok(Some(0.99))
+        );
+
+        histogram.increment(1000.0).unwrap();
+        assert_eq!(histogram.percentile(100.0), Ok(Some(998.4..=1003.51)));
+
+        assert_eq!(histogram.increment(-0.5), Err(Error::OutOfRange));
+        assert_eq!(histogram.increment(f64::NAN), Err(Error::OutOfRange));
+        assert_eq!(histogram.increment(f64::INFINITY), Err(Error::OutOfRange));
+        assert_eq!(histogram.increment(1e8), Err(Error::OutOfRange));
+    }
+}
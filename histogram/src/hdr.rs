@@ -0,0 +1,639 @@
+use crate::{Config, Error, Histogram};
+
+// Conversion to and from the V2 encoding used by HdrHistogram. The layout of
+// the header, with all fields big-endian, is:
+//
+// | field                      | encoding                              |
+// |----------------------------|---------------------------------------|
+// | cookie                     | `u32`, `0x1c849313`                   |
+// | payload length             | `u32`, in bytes                       |
+// | normalizing index offset   | `u32`, must be zero                   |
+// | significant digits         | `u32`, `0..=5`                        |
+// | lowest discernible value   | `u64`, at least one                   |
+// | highest trackable value    | `u64`                                 |
+// | integer to double ratio    | `f64`, must be one                    |
+//
+// The payload is the array of bucket counts, up to the last non-zero count.
+// Each count is a ZigZag encoded LEB128 varint of at most 9 bytes, where the
+// ninth byte holds a full 8 bits. A run of more than one zero count is
+// written as the negated length of the run.
+//
+// The compressed form is a cookie of `0x1c849314` and a `u32` length, followed
+// by that many bytes of the zlib compressed V2 encoding.
+
+/// The cookie which identifies the V2 encoding.
+const COOKIE: u32 = 0x1c84_9313;
+
+/// The cookie which identifies the compressed V2 encoding.
+const COMPRESSED_COOKIE: u32 = 0x1c84_9314;
+
+/// The size of the uncompressed header in bytes.
+const HEADER_LEN: usize = 40;
+
+/// The maximum number of bytes in an encoded count.
+const MAX_VARINT_LEN: usize = 9;
+
+/// The most significant digits which HdrHistogram supports.
+const MAX_SIGNIFICANT_DIGITS: u32 = 5;
+
+/// The bucketing parameters of an HdrHistogram.
+struct Layout {
+    /// The number of subdivisions of each power of two, as a power of two.
+    /// This is equivalent to the grouping power.
+    sub_bucket_half_count_magnitude: u32,
+    /// The power of two of the lowest discernible value.
+    unit_magnitude: u32,
+}
+
+impl Layout {
+    fn new(significant_digits: u32, lowest: u64) -> Self {
+        Self {
+            sub_bucket_half_count_magnitude: sub_bucket_half_count_magnitude(significant_digits),
+            unit_magnitude: lowest.ilog2(),
+        }
+    }
+
+    /// Returns the index in the counts array of the bucket containing the
+    /// value.
+    fn index(&self, value: u64) -> usize {
+        let magnitude = self.sub_bucket_half_count_magnitude;
+        let sub_bucket_mask = (2_u64 << magnitude) - 1;
+
+        let bucket = (63 - magnitude - self.unit_magnitude)
+            - (value | (sub_bucket_mask << self.unit_magnitude)).leading_zeros();
+        let sub_bucket = value >> (bucket + self.unit_magnitude);
+
+        (((bucket as u64 + 1) << magnitude) + sub_bucket - (1 << magnitude)) as usize
+    }
+
+    /// Returns the highest value in the bucket at the index of the counts
+    /// array, or `None` if the index is beyond the largest value.
+    fn highest_value(&self, index: usize) -> Option<u64> {
+        let magnitude = self.sub_bucket_half_count_magnitude;
+        let half = 1_u64 << magnitude;
+
+        let mut bucket = (index as u64 >> magnitude) as i64 - 1;
+        let mut sub_bucket = (index as u64 & (half - 1)) + half;
+
+        if bucket < 0 {
+            sub_bucket -= half;
+            bucket = 0;
+        }
+
+        let shift = u32::try_from(bucket)
+            .ok()?
+            .checked_add(self.unit_magnitude)?;
+        let lowest = (sub_bucket as u128).checked_shl(shift)?;
+        let highest = lowest + 1_u128.checked_shl(shift)? - 1;
+
+        u64::try_from(highest).ok()
+    }
+}
+
+/// Returns the sub-bucket half count magnitude that HdrHistogram uses for the
+/// number of significant digits, which is the power of two that is enough to
+/// distinguish `2 * 10^digits` values.
+fn sub_bucket_half_count_magnitude(significant_digits: u32) -> u32 {
+    let largest = 2 * 10_u64.pow(significant_digits);
+    largest.next_power_of_two().ilog2().max(1) - 1
+}
+
+impl Histogram {
+    /// Encode this histogram in the V2 encoding used by HdrHistogram, which
+    /// can be read by any HdrHistogram implementation.
+    ///
+    /// The number of significant digits is chosen so that the precision of
+    /// HdrHistogram is at least that of this histogram. If the grouping power
+    /// is one which HdrHistogram can represent exactly (0, 4, 7, 10, 14, or
+    /// 17), the buckets are identical. Otherwise, the counts of each bucket
+    /// are recorded at its upper bound.
+    ///
+    /// An error is returned if the grouping power is too high to be
+    /// represented, if there are values above `i64::MAX`, or if any count is
+    /// above `i64::MAX`. A [`Config::logarithmic`] configuration cannot be
+    /// represented either.
+    pub fn to_hdr_v2(&self) -> Result<Vec<u8>, Error> {
+        if self.config.relative_accuracy().is_some() {
+            return Err(Error::IncompatibleParameters);
+        }
+
+        let grouping_power = self.config.grouping_power() as u32;
+
+        let significant_digits = (0..=MAX_SIGNIFICANT_DIGITS)
+            .find(|digits| sub_bucket_half_count_magnitude(*digits) >= grouping_power)
+            .ok_or(Error::IncompatibleParameters)?;
+
+        let highest = self
+            .config
+            .index_to_upper_bound(self.config.total_buckets() - 1);
+        let highest = highest.clamp(2, i64::MAX as u64);
+
+        let layout = Layout::new(significant_digits, 1);
+
+        // gather the counts by their index in the counts array, which is
+        // monotonic in the value
+        let mut counts: Vec<(usize, u64)> = Vec::new();
+
+        for (index, count) in self.buckets.iter().enumerate() {
+            if *count == 0 {
+                continue;
+            }
+
+            let value = self.config.index_to_upper_bound(index);
+            if value > highest || *count > i64::MAX as u64 {
+                return Err(Error::Overflow);
+            }
+
+            let index = layout.index(value);
+
+            match counts.last_mut() {
+                Some((last, total)) if *last == index => {
+                    *total = total
+                        .checked_add(*count)
+                        .filter(|total| *total <= i64::MAX as u64)
+                        .ok_or(Error::Overflow)?;
+                }
+                _ => counts.push((index, *count)),
+            }
+        }
+
+        let mut payload = Vec::new();
+        let mut next = 0;
+
+        for (index, count) in counts {
+            match index - next {
+                0 => {}
+                1 => write_varint(&mut payload, 0),
+                zeros => write_varint(&mut payload, -(zeros as i64)),
+            }
+
+            write_varint(&mut payload, count as i64);
+            next = index + 1;
+        }
+
+        let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+        bytes.extend_from_slice(&COOKIE.to_be_bytes());
+        bytes.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&0_u32.to_be_bytes());
+        bytes.extend_from_slice(&significant_digits.to_be_bytes());
+        bytes.extend_from_slice(&1_u64.to_be_bytes());
+        bytes.extend_from_slice(&highest.to_be_bytes());
+        bytes.extend_from_slice(&1.0_f64.to_be_bytes());
+        bytes.extend_from_slice(&payload);
+
+        Ok(bytes)
+    }
+
+    /// Encode this histogram in the compressed V2 encoding used by
+    /// HdrHistogram, which is the form used in HdrHistogram logs once base64
+    /// encoded. See [`to_hdr_v2`](Histogram::to_hdr_v2) for details.
+    #[cfg(feature = "compression")]
+    pub fn to_hdr_v2_compressed(&self) -> Result<Vec<u8>, Error> {
+        use flate2::write::ZlibEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        let compressed = encoder
+            .write_all(&self.to_hdr_v2()?)
+            .and_then(|_| encoder.finish())
+            .map_err(|_| Error::InvalidEncoding)?;
+
+        let mut bytes = Vec::with_capacity(8 + compressed.len());
+        bytes.extend_from_slice(&COMPRESSED_COOKIE.to_be_bytes());
+        bytes.extend_from_slice(&(compressed.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&compressed);
+
+        Ok(bytes)
+    }
+
+    /// Decode a histogram from the V2 encoding used by HdrHistogram.
+    ///
+    /// The grouping power is chosen to match the precision of the encoded
+    /// histogram and the max value power to cover its highest trackable
+    /// value. When the lowest discernible value is one, the buckets are
+    /// identical. Otherwise, the counts of each encoded bucket are recorded at
+    /// its upper bound.
+    ///
+    /// The compressed form is only supported with the `compression` feature,
+    /// and [`Error::UnsupportedVersion`] is returned for it otherwise. An
+    /// error is also returned if the encoding is malformed or uses features
+    /// which are not supported, such as a normalizing index offset.
+    pub fn from_hdr_v2(bytes: &[u8]) -> Result<Self, Error> {
+        let (cookie, _) = bytes
+            .split_first_chunk::<4>()
+            .ok_or(Error::InvalidEncoding)?;
+
+        match u32::from_be_bytes(*cookie) {
+            COOKIE => decode(bytes),
+            COMPRESSED_COOKIE => decompress(bytes).and_then(|bytes| decode(&bytes)),
+            _ => Err(Error::UnsupportedVersion),
+        }
+    }
+}
+
+#[cfg(feature = "compression")]
+fn decompress(bytes: &[u8]) -> Result<Vec<u8>, Error> {
+    use flate2::read::ZlibDecoder;
+    use std::io::Read;
+
+    let length = bytes
+        .get(4..8)
+        .map(|length| u32::from_be_bytes(length.try_into().unwrap()) as usize)
+        .ok_or(Error::InvalidEncoding)?;
+    let compressed = bytes
+        .get(8..)
+        .filter(|compressed| compressed.len() == length)
+        .ok_or(Error::InvalidEncoding)?;
+
+    let mut decoder = ZlibDecoder::new(compressed);
+
+    // read the header first, so that the rest of the decompressed output can
+    // be limited to the largest payload the header allows, rather than
+    // growing without bound on crafted input
+    let mut decompressed = vec![0; HEADER_LEN];
+    decoder
+        .read_exact(&mut decompressed)
+        .map_err(|_| Error::InvalidEncoding)?;
+
+    let (_, layout, highest) = header(decompressed[..].try_into().unwrap())?;
+    let limit = layout
+        .index(highest)
+        .saturating_add(1)
+        .saturating_mul(MAX_VARINT_LEN);
+
+    let read = decoder
+        .take(limit as u64 + 1)
+        .read_to_end(&mut decompressed)
+        .map_err(|_| Error::InvalidEncoding)?;
+
+    if read > limit {
+        return Err(Error::InvalidEncoding);
+    }
+
+    Ok(decompressed)
+}
+
+#[cfg(not(feature = "compression"))]
+fn decompress(_bytes: &[u8]) -> Result<Vec<u8>, Error> {
+    Err(Error::UnsupportedVersion)
+}
+
+fn decode(bytes: &[u8]) -> Result<Histogram, Error> {
+    let (header, payload) = bytes
+        .split_first_chunk::<HEADER_LEN>()
+        .ok_or(Error::InvalidEncoding)?;
+
+    let (length, layout, highest) = self::header(header)?;
+
+    if length != payload.len() {
+        return Err(Error::InvalidEncoding);
+    }
+
+    let grouping_power = layout.sub_bucket_half_count_magnitude;
+    let max_value_power = (64 - highest.leading_zeros()).max(grouping_power + 1);
+    let config = Config::new(grouping_power as u8, max_value_power as u8)
+        .map_err(|_| Error::InvalidEncoding)?;
+
+    let mut histogram = Histogram::with_config(&config);
+
+    // counts beyond the bucket of the highest trackable value are invalid
+    let last = layout.index(highest);
+
+    let mut payload = payload;
+    let mut index: usize = 0;
+
+    while !payload.is_empty() {
+        let count = read_varint(&mut payload)?;
+
+        if count < 0 {
+            index = index
+                .checked_add(count.unsigned_abs() as usize)
+                .filter(|index| *index <= last)
+                .ok_or(Error::InvalidEncoding)?;
+            continue;
+        }
+
+        if index > last {
+            return Err(Error::InvalidEncoding);
+        }
+
+        if count > 0 {
+            let value = layout.highest_value(index).ok_or(Error::InvalidEncoding)?;
+            let bucket = config
+                .value_to_index(value)
+                .map_err(|_| Error::InvalidEncoding)?;
+
+            histogram.buckets[bucket] = histogram.buckets[bucket].wrapping_add(count as u64);
+        }
+
+        index += 1;
+    }
+
+    Ok(histogram)
+}
+
+/// Validates the header, returning the payload length, the bucketing, and the
+/// highest trackable value.
+fn header(header: &[u8; HEADER_LEN]) -> Result<(usize, Layout, u64), Error> {
+    let u32_at = |at: usize| u32::from_be_bytes(header[at..at + 4].try_into().unwrap());
+    let u64_at = |at: usize| u64::from_be_bytes(header[at..at + 8].try_into().unwrap());
+
+    if u32_at(0) != COOKIE {
+        return Err(Error::UnsupportedVersion);
+    }
+
+    // the features which rely on these are not supported
+    if u32_at(8) != 0 || f64::from_bits(u64_at(32)) != 1.0 {
+        return Err(Error::UnsupportedVersion);
+    }
+
+    let significant_digits = u32_at(12);
+    let lowest = u64_at(16);
+    let highest = u64_at(24);
+
+    if significant_digits > MAX_SIGNIFICANT_DIGITS || lowest == 0 || highest / 2 < lowest {
+        return Err(Error::InvalidEncoding);
+    }
+
+    let layout = Layout::new(significant_digits, lowest);
+
+    // HdrHistogram requires that the sub-buckets of the lowest discernible
+    // value fit within 62 bits, which keeps the index calculations in range
+    if layout.unit_magnitude + layout.sub_bucket_half_count_magnitude > 61 {
+        return Err(Error::InvalidEncoding);
+    }
+
+    Ok((u32_at(4) as usize, layout, highest))
+}
+
+/// Writes a ZigZag encoded varint in the LEB128-64b9B form used by
+/// HdrHistogram.
+fn write_varint(bytes: &mut Vec<u8>, value: i64) {
+    let mut value = ((value << 1) ^ (value >> 63)) as u64;
+
+    for _ in 0..MAX_VARINT_LEN - 1 {
+        if value < 0x80 {
+            bytes.push(value as u8);
+            return;
+        }
+
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+
+    // the last byte holds all of the remaining 8 bits
+    bytes.push(value as u8);
+}
+
+/// Reads a ZigZag encoded varint in the LEB128-64b9B form used by
+/// HdrHistogram, advancing the slice past it.
+fn read_varint(bytes: &mut &[u8]) -> Result<i64, Error> {
+    let mut value: u64 = 0;
+
+    for i in 0..MAX_VARINT_LEN {
+        let (byte, rest) = bytes.split_first().ok_or(Error::InvalidEncoding)?;
+        *bytes = rest;
+
+        if i == MAX_VARINT_LEN - 1 {
+            value |= (*byte as u64) << 56;
+            break;
+        }
+
+        value |= ((byte & 0x7f) as u64) << (7 * i);
+
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+
+    Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hdrhistogram::serialization::{Deserializer, Serializer, V2Serializer};
+
+    #[test]
+    // Tests the varint encoding at the boundaries of each length
+    fn varint() {
+        for value in [
+            0,
+            1,
+            -1,
+            63,
+            -64,
+            64,
+            i64::MAX,
+            i64::MIN,
+            1 << 55,
+            -(1 << 55),
+        ] {
+            let mut bytes = Vec::new();
+            write_varint(&mut bytes, value);
+            assert!(bytes.len() <= MAX_VARINT_LEN);
+
+            let mut slice = bytes.as_slice();
+            assert_eq!(read_varint(&mut slice), Ok(value));
+            assert!(slice.is_empty());
+        }
+    }
+
+    #[test]
+    // Tests that the significant digits map to the same grouping power as
+    // HdrHistogram uses
+    fn precision() {
+        for digits in 0..=MAX_SIGNIFICANT_DIGITS {
+            let hdr =
+                hdrhistogram::Histogram::<u64>::new_with_bounds(1, u64::MAX, digits as u8).unwrap();
+            let layout = Layout::new(digits, 1);
+
+            for value in [0, 1, 1000, 123_456_789, u64::MAX / 3] {
+                assert_eq!(hdr.highest_equivalent(value), {
+                    layout.highest_value(layout.index(value)).unwrap()
+                });
+            }
+        }
+    }
+
+    #[test]
+    // Tests that histograms can be read by HdrHistogram
+    fn export() {
+        let mut histogram = Histogram::new(7, 40).unwrap();
+        for v in 0..10_000 {
+            histogram.add(v * v, v + 1).unwrap();
+        }
+
+        let bytes = histogram.to_hdr_v2().unwrap();
+        let hdr: hdrhistogram::Histogram<u64> = Deserializer::new()
+            .deserialize(&mut bytes.as_slice())
+            .unwrap();
+
+        assert_eq!(hdr.len() as u128, histogram.statistics().count());
+        for percentile in [1.0, 25.0, 50.0, 90.0, 99.0, 99.9, 100.0] {
+            assert_eq!(
+                hdr.value_at_percentile(percentile),
+                histogram.percentile(percentile).unwrap().unwrap().end()
+            );
+        }
+
+        assert_eq!(Histogram::from_hdr_v2(&bytes), Ok(histogram));
+
+        // grouping powers which HdrHistogram cannot match exactly
+        let mut histogram = Histogram::new(5, 64).unwrap();
+        histogram.increment(1000).unwrap();
+        histogram.increment(i64::MAX as u64 + 1).unwrap();
+        assert_eq!(histogram.to_hdr_v2(), Err(Error::Overflow));
+
+        let histogram = Histogram::new(20, 64).unwrap();
+        assert_eq!(histogram.to_hdr_v2(), Err(Error::IncompatibleParameters));
+
+        let histogram = Histogram::with_config(&Config::logarithmic(0.01, 32).unwrap());
+        assert_eq!(histogram.to_hdr_v2(), Err(Error::IncompatibleParameters));
+    }
+
+    #[test]
+    // Tests that histograms written by HdrHistogram can be read
+    fn import() {
+        let mut hdr = hdrhistogram::Histogram::<u64>::new_with_bounds(1, 3_600_000_000, 3).unwrap();
+        for v in 0..10_000 {
+            hdr.record_n(v * 997, v + 1).unwrap();
+        }
+
+        let mut bytes = Vec::new();
+        V2Serializer::new().serialize(&hdr, &mut bytes).unwrap();
+
+        let histogram = Histogram::from_hdr_v2(&bytes).unwrap();
+        assert_eq!(histogram.config().grouping_power(), 10);
+        assert_eq!(histogram.statistics().count(), hdr.len() as u128);
+        for percentile in [1.0, 25.0, 50.0, 90.0, 99.0, 99.9, 100.0] {
+            assert_eq!(
+                hdr.value_at_percentile(percentile),
+                histogram.percentile(percentile).unwrap().unwrap().end()
+            );
+        }
+
+        // a lowest discernible value above one is coarser than the buckets
+        let mut hdr = hdrhistogram::Histogram::<u64>::new_with_bounds(1000, 1 << 40, 2).unwrap();
+        hdr.record(123_456_789).unwrap();
+        let mut bytes = Vec::new();
+        V2Serializer::new().serialize(&hdr, &mut bytes).unwrap();
+        let histogram = Histogram::from_hdr_v2(&bytes).unwrap();
+        assert!(histogram
+            .percentile(50.0)
+            .unwrap()
+            .unwrap()
+            .range()
+            .contains(&hdr.highest_equivalent(123_456_789)));
+
+        assert_eq!(Histogram::from_hdr_v2(&[]), Err(Error::InvalidEncoding));
+        assert_eq!(
+            Histogram::from_hdr_v2(&bytes[..bytes.len() - 1]),
+            Err(Error::InvalidEncoding)
+        );
+        assert_eq!(
+            Histogram::from_hdr_v2(&[0x1c, 0x84, 0x93, 0x03]),
+            Err(Error::UnsupportedVersion)
+        );
+    }
+
+    /// Returns an uncompressed encoding with the provided header fields and
+    /// payload.
+    fn encoding(digits: u32, lowest: u64, highest: u64, payload: &[i64]) -> Vec<u8> {
+        let mut varints = Vec::new();
+        for value in payload {
+            write_varint(&mut varints, *value);
+        }
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&COOKIE.to_be_bytes());
+        bytes.extend_from_slice(&(varints.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&0_u32.to_be_bytes());
+        bytes.extend_from_slice(&digits.to_be_bytes());
+        bytes.extend_from_slice(&lowest.to_be_bytes());
+        bytes.extend_from_slice(&highest.to_be_bytes());
+        bytes.extend_from_slice(&1.0_f64.to_be_bytes());
+        bytes.extend_from_slice(&varints);
+        bytes
+    }
+
+    #[test]
+    // Tests that crafted headers and payloads are rejected
+    fn malformed() {
+        // a run of zeros beyond the highest trackable value
+        let bytes = encoding(3, 1, 3_600_000_000, &[-(1 << 40), 1]);
+        assert_eq!(Histogram::from_hdr_v2(&bytes), Err(Error::InvalidEncoding));
+
+        // a count just beyond the highest trackable value
+        let last = Layout::new(3, 1).index(3_600_000_000) as i64;
+        let bytes = encoding(3, 1, 3_600_000_000, &[-last, 1]);
+        assert!(Histogram::from_hdr_v2(&bytes).is_ok());
+        let bytes = encoding(3, 1, 3_600_000_000, &[-last, 0, 1]);
+        assert_eq!(Histogram::from_hdr_v2(&bytes), Err(Error::InvalidEncoding));
+
+        // the sub-buckets of the lowest discernible value exceed 62 bits
+        let bytes = encoding(5, 1 << 50, 1 << 62, &[]);
+        assert_eq!(Histogram::from_hdr_v2(&bytes), Err(Error::InvalidEncoding));
+        let bytes = encoding(5, 1 << 44, 1 << 62, &[]);
+        assert!(Histogram::from_hdr_v2(&bytes).is_ok());
+        let bytes = encoding(0, 1 << 63, u64::MAX, &[]);
+        assert_eq!(Histogram::from_hdr_v2(&bytes), Err(Error::InvalidEncoding));
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    // Tests the compressed encoding in both directions
+    fn compressed() {
+        use flate2::write::ZlibEncoder;
+        use flate2::Compression;
+        use hdrhistogram::serialization::V2DeflateSerializer;
+        use std::io::Write;
+
+        let mut histogram = Histogram::new(10, 40).unwrap();
+        for v in 0..1000 {
+            histogram.increment(v * 1000).unwrap();
+        }
+
+        let bytes = histogram.to_hdr_v2_compressed().unwrap();
+        let hdr: hdrhistogram::Histogram<u64> = Deserializer::new()
+            .deserialize(&mut bytes.as_slice())
+            .unwrap();
+        assert_eq!(hdr.len(), 1000);
+        assert_eq!(Histogram::from_hdr_v2(&bytes), Ok(histogram.clone()));
+
+        let mut bytes = Vec::new();
+        V2DeflateSerializer::new()
+            .serialize(&hdr, &mut bytes)
+            .unwrap();
+        assert_eq!(Histogram::from_hdr_v2(&bytes), Ok(histogram.clone()));
+
+        // output beyond the largest payload for the header is rejected rather
+        // than decompressed in full
+        let mut encoded = histogram.to_hdr_v2().unwrap();
+        encoded.resize(encoded.len() + (64 << 20), 0);
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&encoded).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&COMPRESSED_COOKIE.to_be_bytes());
+        bytes.extend_from_slice(&(compressed.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&compressed);
+        assert_eq!(Histogram::from_hdr_v2(&bytes), Err(Error::InvalidEncoding));
+
+        // a header which cannot be represented is rejected before the limit
+        // on the payload is calculated
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(&encoding(5, 1 << 50, 1 << 62, &[]))
+            .unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&COMPRESSED_COOKIE.to_be_bytes());
+        bytes.extend_from_slice(&(compressed.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&compressed);
+        assert_eq!(Histogram::from_hdr_v2(&bytes), Err(Error::InvalidEncoding));
+    }
+}
@@ -0,0 +1,281 @@
+use crate::{Bucket, Config, Error, Histogram, WindowedHistogram};
+use clocksource::precise::{Duration, Instant};
+
+/// A heatmap is a time-indexed series of histograms, which can be used to see
+/// how a distribution changes over time.
+///
+/// Each slice of the heatmap is a [`crate::Histogram`] covering a fixed
+/// duration, the `resolution`, and the heatmap retains slices covering the
+/// most recent `span`. Unlike the [`crate::WindowedHistogram`] it is built
+/// on, the heatmap is primarily read one slice at a time, for example to
+/// render each slice as a row of a waterfall plot or to follow a percentile
+/// over time.
+#[derive(Clone, Debug)]
+pub struct Heatmap {
+    window: WindowedHistogram,
+}
+
+impl Heatmap {
+    /// Construct a new heatmap from the provided parameters. See the
+    /// documentation for [`crate::Config`] to understand their meaning.
+    ///
+    /// The `span` is the total duration of history to retain and the
+    /// `resolution` is the duration covered by each slice. The span must be a
+    /// non-zero multiple of the resolution.
+    pub fn new(
+        grouping_power: u8,
+        max_value_power: u8,
+        span: Duration,
+        resolution: Duration,
+    ) -> Result<Self, Error> {
+        let config = Config::new(grouping_power, max_value_power)?;
+
+        Self::with_config(&config, span, resolution)
+    }
+
+    /// Creates a new heatmap using a provided [`crate::Config`]. The first
+    /// slice begins at the current instant.
+    pub fn with_config(
+        config: &Config,
+        span: Duration,
+        resolution: Duration,
+    ) -> Result<Self, Error> {
+        Self::with_start(config, span, resolution, Instant::now())
+    }
+
+    /// Creates a new heatmap using a provided [`crate::Config`] with the first
+    /// slice beginning at the provided instant.
+    pub fn with_start(
+        config: &Config,
+        span: Duration,
+        resolution: Duration,
+        start: Instant,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            window: WindowedHistogram::with_start(config, span, resolution, start)?,
+        })
+    }
+
+    /// Increment the counter for the bucket corresponding to the provided value
+    /// by one in the slice containing the provided instant.
+    pub fn increment(&mut self, time: Instant, value: u64) -> Result<(), Error> {
+        self.window.increment(time, value)
+    }
+
+    /// Add some count to the counter for the bucket corresponding to the
+    /// provided value in the slice containing the provided instant.
+    ///
+    /// An error is returned if the instant is older than the retained slices.
+    pub fn add(&mut self, time: Instant, value: u64, count: u64) -> Result<(), Error> {
+        self.window.add(time, value, count)
+    }
+
+    /// Advances the heatmap so that the newest slice contains the provided
+    /// instant, adding empty slices as needed.
+    pub fn advance_to(&mut self, time: Instant) {
+        self.window.advance_to(time)
+    }
+
+    /// Returns the number of slices which are retained, which is less than the
+    /// span divided by the resolution until the heatmap has been filled.
+    pub fn active_slices(&self) -> usize {
+        self.window.active_slices()
+    }
+
+    /// Returns the number of buckets in each slice.
+    pub fn buckets(&self) -> usize {
+        self.window.config().total_buckets()
+    }
+
+    /// Returns the bucket configuration shared by every slice.
+    pub fn config(&self) -> Config {
+        self.window.config()
+    }
+
+    /// Returns the duration covered by each slice.
+    pub fn resolution(&self) -> Duration {
+        self.window.resolution()
+    }
+
+    /// Returns the total duration covered by the heatmap once it is filled.
+    pub fn span(&self) -> Duration {
+        self.window.span()
+    }
+
+    /// Returns the instant at which the oldest retained slice begins.
+    pub fn start_at(&self) -> Instant {
+        self.window.start_at()
+    }
+
+    /// Returns the instant at which the newest slice ends.
+    pub fn end_at(&self) -> Instant {
+        self.window.end_at()
+    }
+
+    /// Returns an iterator across the retained slices from oldest to newest.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            offset: 0,
+            heatmap: self,
+        }
+    }
+
+    /// Returns a histogram which merges every retained slice.
+    pub fn summary(&self) -> Histogram {
+        self.window.window()
+    }
+
+    /// Return a collection of percentiles for each retained slice, from oldest
+    /// to newest, along with the instant at which the slice begins. Slices
+    /// which are empty have no percentiles.
+    ///
+    /// Each percentile should be in the inclusive range `0.0..=100.0`.
+    #[allow(clippy::type_complexity)]
+    pub fn percentiles(
+        &self,
+        percentiles: &[f64],
+    ) -> Result<Vec<(Instant, Option<Vec<(f64, Bucket)>>)>, Error> {
+        self.iter()
+            .map(|slice| Ok((slice.start(), slice.histogram().percentiles(percentiles)?)))
+            .collect()
+    }
+
+    /// Return a single percentile for each retained slice, from oldest to
+    /// newest, along with the instant at which the slice begins.
+    ///
+    /// The percentile should be in the inclusive range `0.0..=100.0`.
+    pub fn percentile(&self, percentile: f64) -> Result<Vec<(Instant, Option<Bucket>)>, Error> {
+        self.iter()
+            .map(|slice| Ok((slice.start(), slice.histogram().percentile(percentile)?)))
+            .collect()
+    }
+}
+
+impl<'a> IntoIterator for &'a Heatmap {
+    type Item = Slice<'a>;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// A single slice of a heatmap, which is the histogram of observations over
+/// one resolution of time.
+#[derive(Clone, Copy, Debug)]
+pub struct Slice<'a> {
+    start: Instant,
+    resolution: Duration,
+    histogram: &'a Histogram,
+}
+
+impl<'a> Slice<'a> {
+    /// Returns the instant at which the slice begins.
+    pub fn start(&self) -> Instant {
+        self.start
+    }
+
+    /// Returns the instant at which the slice ends.
+    pub fn end(&self) -> Instant {
+        self.start + self.resolution
+    }
+
+    /// Returns the histogram of observations in the slice.
+    pub fn histogram(&self) -> &'a Histogram {
+        self.histogram
+    }
+}
+
+impl<'a> IntoIterator for Slice<'a> {
+    type Item = Bucket;
+    type IntoIter = crate::standard::Iter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.histogram.into_iter()
+    }
+}
+
+/// An iterator across the slices of a heatmap.
+pub struct Iter<'a> {
+    offset: usize,
+    heatmap: &'a Heatmap,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = Slice<'a>;
+
+    fn next(&mut self) -> Option<<Self as std::iter::Iterator>::Item> {
+        let (start, histogram) = self.heatmap.window.slice(self.offset)?;
+
+        self.offset += 1;
+
+        Some(Slice {
+            start,
+            resolution: self.heatmap.resolution(),
+            histogram,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    // Tests that slices are iterated from oldest to newest
+    fn slices() {
+        let start = Instant::now();
+        let mut heatmap = Heatmap::with_start(
+            &Config::new(7, 64).unwrap(),
+            Duration::from_secs(4),
+            Duration::from_secs(1),
+            start,
+        )
+        .unwrap();
+
+        assert_eq!(heatmap.active_slices(), 1);
+        assert_eq!(heatmap.buckets(), heatmap.config().total_buckets());
+
+        for second in 0..6 {
+            let time = start + Duration::from_secs(second);
+            heatmap.add(time, second as u64 * 100, 1).unwrap();
+        }
+
+        assert_eq!(heatmap.active_slices(), 4);
+        assert_eq!(heatmap.start_at(), start + Duration::from_secs(2));
+
+        let slices: Vec<Slice> = heatmap.iter().collect();
+        assert_eq!(slices.len(), 4);
+        assert_eq!(slices[0].start(), start + Duration::from_secs(2));
+        assert_eq!(slices[3].end(), heatmap.end_at());
+        assert_eq!(slices[0].into_iter().map(|b| b.count()).sum::<u64>(), 1);
+        assert_eq!(heatmap.summary().statistics().count(), 4);
+    }
+
+    #[test]
+    // Tests percentiles over time
+    fn percentiles() {
+        let start = Instant::now();
+        let mut heatmap = Heatmap::with_start(
+            &Config::new(7, 64).unwrap(),
+            Duration::from_secs(4),
+            Duration::from_secs(1),
+            start,
+        )
+        .unwrap();
+
+        heatmap.increment(start, 10).unwrap();
+        heatmap
+            .increment(start + Duration::from_secs(2), 20)
+            .unwrap();
+
+        let series = heatmap.percentile(50.0).unwrap();
+        assert_eq!(series.len(), 3);
+        assert_eq!(series[0].0, start);
+        assert_eq!(series[0].1.as_ref().map(|b| b.range()), Some(10..=10));
+        assert_eq!(series[1].1, None);
+        assert_eq!(series[2].1.as_ref().map(|b| b.range()), Some(20..=20));
+
+        assert_eq!(heatmap.percentile(101.0), Err(Error::InvalidPercentile));
+    }
+}
@@ -13,14 +13,55 @@
 
 mod atomic;
 mod bucket;
+mod cdf;
+mod compact;
 mod config;
+mod decaying;
+mod distance;
+mod distribution;
+mod encoding;
 mod errors;
+mod exact;
+mod float;
+mod hdr;
+mod heatmap;
+mod paged;
+mod percentile;
+pub mod prometheus;
+#[cfg(test)]
+mod properties;
+mod rebucket;
+mod sharded;
+mod shared;
+mod signed;
+mod slo;
+mod snapshot;
 mod sparse;
 mod standard;
+mod statistics;
+mod traits;
+mod windowed;
 
 pub use atomic::AtomicHistogram;
 pub use bucket::Bucket;
+pub use cdf::{Cumulative, CumulativeBucket};
+pub use compact::{CompactAtomicHistogram, CompactHistogram, CounterWidth};
 pub use config::Config;
+pub use decaying::DecayingHistogram;
 pub use errors::Error;
+pub use exact::Exact;
+pub use float::FloatHistogram;
+pub use heatmap::{Heatmap, Slice};
+pub use paged::PagedHistogram;
+pub use percentile::Interpolation;
+pub use rebucket::{Overflow, Rebucketing};
+pub use sharded::ShardedHistogram;
+pub use shared::SharedHistogram;
+pub use signed::SignedHistogram;
+pub use slo::{Evaluation, Objective};
+pub use snapshot::Snapshot;
 pub use sparse::SparseHistogram;
 pub use standard::Histogram;
+pub use statistics::Statistics;
+pub use traits::{ReadHistogram, RecordHistogram};
+pub use windowed::WindowedHistogram;
@@ -0,0 +1,283 @@
+use crate::{Config, Error, SparseHistogram};
+use core::ptr::null_mut;
+use core::sync::atomic::{AtomicPtr, AtomicU64, Ordering};
+
+/// The number of counters in a page, which is the unit of allocation.
+const PAGE_COUNTERS: usize = 64;
+
+/// The number of pages in each table of the second level.
+const TABLE_PAGES: usize = 64;
+
+/// The number of counters covered by each table.
+const TABLE_COUNTERS: usize = PAGE_COUNTERS * TABLE_PAGES;
+
+/// A page of counters for consecutive buckets.
+struct Page([AtomicU64; PAGE_COUNTERS]);
+
+impl Page {
+    fn new() -> Self {
+        Self(core::array::from_fn(|_| AtomicU64::new(0)))
+    }
+}
+
+/// A table of pages which are allocated when first touched.
+struct Table([AtomicPtr<Page>; TABLE_PAGES]);
+
+impl Table {
+    fn new() -> Self {
+        Self(core::array::from_fn(|_| AtomicPtr::new(null_mut())))
+    }
+}
+
+impl Drop for Table {
+    fn drop(&mut self) {
+        for page in &mut self.0 {
+            free(page);
+        }
+    }
+}
+
+/// Returns the allocation behind the pointer, if there is one.
+fn get<T>(pointer: &AtomicPtr<T>) -> Option<&T> {
+    // SAFETY: a non-null pointer is from `Box::into_raw` and is only freed by
+    // `free`, which requires exclusive access to the pointer
+    unsafe { pointer.load(Ordering::Acquire).as_ref() }
+}
+
+/// Returns the allocation behind the pointer, allocating it first if the
+/// pointer is null. Threads which race to allocate it each make an allocation
+/// and all but the one which is stored free theirs, so no thread waits for
+/// another.
+fn get_or_allocate<T>(pointer: &AtomicPtr<T>, new: impl FnOnce() -> T) -> &T {
+    if let Some(value) = get(pointer) {
+        return value;
+    }
+
+    let allocated = Box::into_raw(Box::new(new()));
+
+    match pointer.compare_exchange(null_mut(), allocated, Ordering::AcqRel, Ordering::Acquire) {
+        // SAFETY: the allocation is now owned by the pointer, see `get`
+        Ok(_) => unsafe { &*allocated },
+        Err(current) => {
+            // SAFETY: the allocation was never shared, and the pointer which
+            // won the race is from `Box::into_raw`, see `get`
+            unsafe {
+                drop(Box::from_raw(allocated));
+                &*current
+            }
+        }
+    }
+}
+
+/// Frees the allocation behind the pointer, if there is one, and resets it to
+/// null.
+fn free<T>(pointer: &mut AtomicPtr<T>) {
+    let allocated = core::mem::replace(pointer.get_mut(), null_mut());
+
+    if !allocated.is_null() {
+        // SAFETY: the pointer is from `Box::into_raw` and, with exclusive
+        // access, no references to the allocation remain
+        drop(unsafe { Box::from_raw(allocated) });
+    }
+}
+
+/// A histogram with atomic 64bit counters which are only allocated once a
+/// value is recorded in a nearby bucket.
+///
+/// An [`crate::AtomicHistogram`] allocates a counter for every bucket up
+/// front, which for wide configurations is megabytes per histogram even
+/// though most buckets are never used. Here, the counters are in pages which
+/// are held in a two-level table, and both the pages and the tables are only
+/// allocated when first touched. Memory use grows with the number of distinct
+/// regions of values that are recorded rather than with the configuration.
+///
+/// Recording is lock-free. Once a page is allocated, it is a single atomic
+/// increment after two atomic loads. Threads which race to allocate the same
+/// page each allocate one and all but one of them free theirs. Pages are only
+/// freed when the histogram is dropped, not when drained.
+///
+/// As with the atomic histogram, a snapshot must be taken to report
+/// percentiles, which is a [`crate::SparseHistogram`] holding only the
+/// non-empty buckets.
+pub struct PagedHistogram {
+    config: Config,
+    tables: Box<[AtomicPtr<Table>]>,
+}
+
+impl Drop for PagedHistogram {
+    fn drop(&mut self) {
+        for table in self.tables.iter_mut() {
+            free(table);
+        }
+    }
+}
+
+impl PagedHistogram {
+    /// Construct a new paged histogram from the provided parameters. See the
+    /// documentation for [`crate::Config`] to understand their meaning.
+    pub fn new(grouping_power: u8, max_value_power: u8) -> Result<Self, Error> {
+        let config = Config::new(grouping_power, max_value_power)?;
+
+        Ok(Self::with_config(&config))
+    }
+
+    /// Creates a new paged histogram using a provided [`crate::Config`]. No
+    /// pages are allocated until values are recorded.
+    pub fn with_config(config: &Config) -> Self {
+        let tables = config.total_buckets().div_ceil(TABLE_COUNTERS);
+
+        let mut table = Vec::with_capacity(tables);
+        table.resize_with(tables, || AtomicPtr::new(null_mut()));
+
+        Self {
+            config: *config,
+            tables: table.into(),
+        }
+    }
+
+    /// Increment the bucket that contains the value by one.
+    pub fn increment(&self, value: u64) -> Result<(), Error> {
+        self.add(value, 1)
+    }
+
+    /// Increment the bucket that contains the value by some count, allocating
+    /// the page which holds its counter if needed.
+    pub fn add(&self, value: u64, count: u64) -> Result<(), Error> {
+        let index = self.config.value_to_index(value)?;
+        self.add_to_index(index, count);
+        Ok(())
+    }
+
+    /// Increment the bucket with the index by some count.
+    pub(crate) fn add_to_index(&self, index: usize, count: u64) {
+        let table = get_or_allocate(&self.tables[index / TABLE_COUNTERS], Table::new);
+        let page = get_or_allocate(&table.0[(index / PAGE_COUNTERS) % TABLE_PAGES], Page::new);
+
+        page.0[index % PAGE_COUNTERS].fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Returns the bucket configuration of the histogram.
+    pub fn config(&self) -> Config {
+        self.config
+    }
+
+    /// Returns the number of buckets which have counters allocated, which is
+    /// a multiple of the page size.
+    pub fn allocated_buckets(&self) -> usize {
+        self.pages().count() * PAGE_COUNTERS
+    }
+
+    // NOTE: once stabilized, `target_has_atomic_load_store` is more correct. https://github.com/rust-lang/rust/issues/94039
+    #[cfg(target_has_atomic = "64")]
+    /// Drains the bucket values into a new `SparseHistogram`
+    ///
+    /// Unlike [`load`](PagedHistogram::load), this method will reset all
+    /// bucket values to zero. This uses [`AtomicU64::swap`] and is not
+    /// available on platforms where [`AtomicU64::swap`] is not available.
+    pub fn drain(&self) -> SparseHistogram {
+        self.read(|counter| counter.swap(0, Ordering::Relaxed))
+    }
+
+    /// Read the bucket values into a new `SparseHistogram`
+    pub fn load(&self) -> SparseHistogram {
+        self.read(|counter| counter.load(Ordering::Relaxed))
+    }
+
+    fn read(&self, read: impl Fn(&AtomicU64) -> u64) -> SparseHistogram {
+        let mut histogram = SparseHistogram::with_config(&self.config);
+
+        for (index, counter) in self.counters() {
+            let count = read(counter);
+            if count != 0 {
+                histogram.index.push(index);
+                histogram.count.push(count);
+            }
+        }
+
+        histogram
+    }
+
+    /// Returns the allocated pages along with the index of their first
+    /// bucket, in increasing order of index.
+    fn pages(&self) -> impl Iterator<Item = (usize, &Page)> + Clone {
+        self.tables
+            .iter()
+            .enumerate()
+            .filter_map(|(t, table)| Some((t, get(table)?)))
+            .flat_map(|(t, table)| {
+                table.0.iter().enumerate().filter_map(move |(p, page)| {
+                    Some((t * TABLE_COUNTERS + p * PAGE_COUNTERS, get(page)?))
+                })
+            })
+    }
+
+    /// Returns the allocated counters along with the index of their bucket, in
+    /// increasing order of index.
+    pub(crate) fn counters(&self) -> impl Iterator<Item = (usize, &AtomicU64)> + Clone {
+        let total = self.config.total_buckets();
+
+        self.pages()
+            .flat_map(|(first, page)| {
+                page.0
+                    .iter()
+                    .enumerate()
+                    .map(move |(offset, counter)| (first + offset, counter))
+            })
+            // the last page may extend beyond the last bucket
+            .take_while(move |(index, _)| *index < total)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Histogram;
+
+    #[test]
+    // Tests that pages are only allocated when touched
+    fn allocation() {
+        let histogram = PagedHistogram::new(14, 64).unwrap();
+        assert_eq!(histogram.allocated_buckets(), 0);
+        assert_eq!(histogram.load(), SparseHistogram::new(14, 64).unwrap());
+
+        histogram.increment(1).unwrap();
+        histogram.increment(2).unwrap();
+        assert_eq!(histogram.allocated_buckets(), PAGE_COUNTERS);
+
+        histogram.increment(u64::MAX).unwrap();
+        assert_eq!(histogram.allocated_buckets(), 2 * PAGE_COUNTERS);
+
+        let mut expected = Histogram::new(14, 64).unwrap();
+        for value in [1, 2, u64::MAX] {
+            expected.increment(value).unwrap();
+        }
+        assert_eq!(histogram.load(), SparseHistogram::from(&expected));
+    }
+
+    #[cfg(target_has_atomic = "64")]
+    #[test]
+    // Tests that concurrent recording matches a plain histogram and that
+    // drain resets the counters
+    fn drain() {
+        let histogram = PagedHistogram::new(7, 64).unwrap();
+
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for value in 0..10_000 {
+                        histogram.increment(value * 997).unwrap();
+                    }
+                });
+            }
+        });
+
+        let mut expected = Histogram::new(7, 64).unwrap();
+        for value in 0..10_000 {
+            expected.add(value * 997, 4).unwrap();
+        }
+
+        assert_eq!(histogram.drain(), SparseHistogram::from(&expected));
+        assert_eq!(histogram.load(), SparseHistogram::new(7, 64).unwrap());
+        assert!(histogram.allocated_buckets() > 0);
+    }
+}
@@ -0,0 +1,300 @@
+use crate::{Bucket, Config, Error};
+
+/// The strategy used to estimate a single value for a percentile from the
+/// bucket which contains it.
+///
+/// Since a bucket only records that observations fell somewhere within its
+/// range, any value in the range is a valid estimate. The strategies trade off
+/// bias in different directions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Use the inclusive lower bound of the bucket. Never overestimates.
+    Lower,
+    /// Use the inclusive upper bound of the bucket. Never underestimates.
+    #[default]
+    Upper,
+    /// Use the midpoint of the bucket's range.
+    Midpoint,
+    /// Interpolate linearly within the bucket's range according to the rank of
+    /// the percentile among the observations in the bucket, assuming they are
+    /// evenly distributed.
+    Linear,
+}
+
+/// The position of a percentile within a histogram.
+pub(crate) struct Rank {
+    /// The percentile that was searched for.
+    pub percentile: f64,
+    /// The index of the bucket which contains the percentile.
+    pub index: usize,
+    /// The count of the bucket which contains the percentile.
+    pub count: u64,
+    /// The total count of all buckets before the containing bucket.
+    pub before: u128,
+    /// The rank of the percentile among all observations, starting at one.
+    pub rank: u128,
+}
+
+impl Interpolation {
+    /// Estimate the value at the provided rank.
+    pub(crate) fn value(&self, config: &Config, rank: &Rank) -> f64 {
+        let lower = config.index_to_lower_bound(rank.index) as f64;
+        let upper = config.index_to_upper_bound(rank.index) as f64;
+
+        self.estimate(lower, upper, rank)
+    }
+
+    /// Estimate the value at the provided rank, which falls in a bucket with
+    /// the provided inclusive bounds.
+    pub(crate) fn estimate(&self, lower: f64, upper: f64, rank: &Rank) -> f64 {
+        match self {
+            Self::Lower => lower,
+            Self::Upper => upper,
+            Self::Midpoint => (lower + upper) / 2.0,
+            Self::Linear => {
+                let fraction = (rank.rank - rank.before) as f64 / rank.count as f64;
+                lower + (upper - lower) * fraction
+            }
+        }
+    }
+}
+
+/// Finds the bucket which contains each of the provided percentiles by walking
+/// the non-zero buckets, which must be in increasing order of index.
+///
+/// Returns `None` if there are no observations. The results are sorted by the
+/// percentile. The zeroth percentile is the first observation.
+pub(crate) fn ranks<I>(percentiles: &[f64], buckets: I) -> Result<Option<Vec<Rank>>, Error>
+where
+    I: Iterator<Item = (usize, u64)> + Clone,
+{
+    let mut output = Vec::with_capacity(percentiles.len());
+
+    Ok(ranks_into(
+        percentiles,
+        buckets,
+        &mut output,
+        |rank| rank,
+        |rank| rank.percentile,
+    )?
+    .then_some(output))
+}
+
+/// Finds the bucket which contains each of the provided percentiles by walking
+/// the non-zero buckets, which must be in increasing order of index. The
+/// results replace the contents of the output, sorted by the percentile.
+///
+/// Nothing is allocated once the output has enough capacity and the
+/// percentiles are only sorted if they are not already in order. Returns false
+/// if there are no observations, in which case the output is left empty.
+pub(crate) fn percentiles_into<I>(
+    config: &Config,
+    percentiles: &[f64],
+    buckets: I,
+    output: &mut Vec<(f64, Bucket)>,
+) -> Result<bool, Error>
+where
+    I: Iterator<Item = (usize, u64)> + Clone,
+{
+    ranks_into(
+        percentiles,
+        buckets,
+        output,
+        |rank| {
+            (
+                rank.percentile,
+                Bucket {
+                    count: rank.count,
+                    range: config.index_to_range(rank.index),
+                },
+            )
+        },
+        |(percentile, _)| *percentile,
+    )
+}
+
+/// Finds the rank of each of the provided percentiles by walking the non-zero
+/// buckets, which must be in increasing order of index, and writes an entry
+/// made from each rank into the output, replacing its contents. The entries
+/// are sorted by the percentile, which is read back from an entry with the
+/// provided function.
+///
+/// The output is first filled with an entry for each percentile, which is
+/// sorted in place so that nothing else is allocated, and then each entry is
+/// replaced once its rank is found. Returns false if there are no
+/// observations, in which case the output is left empty.
+fn ranks_into<I, T>(
+    percentiles: &[f64],
+    buckets: I,
+    output: &mut Vec<T>,
+    entry: impl Fn(Rank) -> T,
+    percentile: impl Fn(&T) -> f64,
+) -> Result<bool, Error>
+where
+    I: Iterator<Item = (usize, u64)> + Clone,
+{
+    output.clear();
+
+    for percentile in percentiles {
+        if !(0.0..=100.0).contains(percentile) {
+            return Err(Error::InvalidPercentile);
+        }
+    }
+
+    let total: u128 = buckets.clone().map(|(_, count)| count as u128).sum();
+
+    // empty histogram, no percentiles available
+    if total == 0 {
+        return Ok(false);
+    }
+
+    output.extend(percentiles.iter().map(|percentile| {
+        entry(Rank {
+            percentile: *percentile,
+            index: 0,
+            count: 0,
+            before: 0,
+            rank: 0,
+        })
+    }));
+
+    // sort in place, which does not allocate, so the percentiles can be found
+    // in a single pass
+    if !is_sorted(percentiles) {
+        output.sort_unstable_by(|a, b| percentile(a).partial_cmp(&percentile(b)).unwrap());
+    }
+
+    let mut position = 0;
+    let mut next = match output.first() {
+        Some(first) => rank(percentile(first), total),
+        None => return Ok(true),
+    };
+    let mut before: u128 = 0;
+
+    for (index, count) in buckets.filter(|(_, count)| *count != 0) {
+        let seen = before + count as u128;
+
+        // the rank is only computed once for each percentile, rather than for
+        // every bucket
+        while next <= seen {
+            output[position] = entry(Rank {
+                percentile: percentile(&output[position]),
+                index,
+                count,
+                before,
+                rank: next,
+            });
+            position += 1;
+
+            match output.get(position) {
+                Some(entry) => next = rank(percentile(entry), total),
+                None => return Ok(true),
+            }
+        }
+
+        before = seen;
+    }
+
+    Ok(true)
+}
+
+/// Returns true if the percentiles are in increasing order.
+fn is_sorted(percentiles: &[f64]) -> bool {
+    percentiles.windows(2).all(|pair| pair[0] <= pair[1])
+}
+
+/// Returns the rank of the percentile among the total observations.
+fn rank(percentile: f64, total: u128) -> u128 {
+    ((percentile / 100.0 * total as f64).ceil() as u128).clamp(1, total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    // Test the estimates for each strategy
+    fn estimates() {
+        let config = Config::new(2, 8).unwrap();
+        let buckets = [(3, 2), (8, 4)];
+
+        // bucket 8 covers 8..=9
+        let ranks = ranks(&[100.0, 50.0, 0.0, 75.0], buckets.iter().copied())
+            .unwrap()
+            .unwrap();
+
+        let percentiles: Vec<f64> = ranks.iter().map(|r| r.percentile).collect();
+        assert_eq!(percentiles, vec![0.0, 50.0, 75.0, 100.0]);
+
+        let value = |interpolation: Interpolation| -> Vec<f64> {
+            ranks
+                .iter()
+                .map(|r| interpolation.value(&config, r))
+                .collect()
+        };
+
+        assert_eq!(value(Interpolation::Lower), vec![3.0, 8.0, 8.0, 8.0]);
+        assert_eq!(value(Interpolation::Upper), vec![3.0, 9.0, 9.0, 9.0]);
+        assert_eq!(value(Interpolation::Midpoint), vec![3.0, 8.5, 8.5, 8.5]);
+        assert_eq!(value(Interpolation::Linear), vec![3.0, 8.25, 8.75, 9.0]);
+    }
+
+    #[test]
+    // Test that results are sorted whether or not the input is
+    fn into() {
+        let config = Config::new(2, 8).unwrap();
+        let buckets = [(0, 0), (3, 2), (8, 4)];
+        let mut output = Vec::new();
+
+        for percentiles in [[0.0, 50.0, 100.0], [100.0, 0.0, 50.0]] {
+            assert_eq!(
+                percentiles_into(&config, &percentiles, buckets.iter().copied(), &mut output),
+                Ok(true)
+            );
+
+            let found: Vec<(f64, u64)> = output.iter().map(|(p, b)| (*p, b.end())).collect();
+            assert_eq!(found, vec![(0.0, 3), (50.0, 9), (100.0, 9)]);
+        }
+
+        assert_eq!(
+            percentiles_into(&config, &[], buckets.iter().copied(), &mut output),
+            Ok(true)
+        );
+        assert_eq!(
+            percentiles_into(&config, &[50.0], [].into_iter(), &mut output),
+            Ok(false)
+        );
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    // Test that ranks and buckets agree, including for the zeroth percentile
+    fn consistent() {
+        let config = Config::new(2, 8).unwrap();
+        let buckets = [(3, 2), (8, 4), (12, 1)];
+        let percentiles = [0.0, 0.1, 33.3, 50.0, 99.9, 100.0];
+        let mut output = Vec::new();
+
+        let ranks = ranks(&percentiles, buckets.iter().copied())
+            .unwrap()
+            .unwrap();
+        assert!(
+            percentiles_into(&config, &percentiles, buckets.iter().copied(), &mut output).unwrap()
+        );
+
+        assert_eq!(ranks[0].rank, 1);
+        assert_eq!(ranks.len(), output.len());
+        for (rank, (percentile, bucket)) in ranks.iter().zip(output.iter()) {
+            assert_eq!(rank.percentile, *percentile);
+            assert_eq!(config.index_to_range(rank.index), bucket.range);
+            assert_eq!(rank.count, bucket.count);
+        }
+    }
+
+    #[test]
+    // Test that there are no results for empty histograms
+    fn empty() {
+        assert!(ranks(&[50.0], [].into_iter()).unwrap().is_none());
+        assert!(ranks(&[101.0], [].into_iter()).is_err());
+    }
+}
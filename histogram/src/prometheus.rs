@@ -0,0 +1,474 @@
+//! Export histograms for consumption by Prometheus.
+//!
+//! Two representations are supported:
+//! * classic histograms with cumulative `le` buckets, rendered in the
+//!   OpenMetrics text format by [`write_openmetrics`]
+//! * native histograms, which use exponential buckets described by a schema,
+//!   sparse spans, and delta-encoded counts, as produced by
+//!   [`NativeHistogram::try_from`]. The fields mirror the Prometheus protobuf
+//!   message so they can be copied directly into it.
+
+use crate::{Bucket, Config, Error, Histogram, SparseHistogram};
+use core::fmt::{Result, Write};
+
+/// The finest resolution schema supported by Prometheus native histograms.
+const MAX_SCHEMA: u8 = 8;
+
+/// The maximum number of empty buckets to include in a span rather than
+/// starting a new span. This matches the behavior of the Prometheus client.
+const MAX_SPAN_GAP: u32 = 2;
+
+/// A contiguous run of native histogram buckets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BucketSpan {
+    /// The gap to the previous span, or the starting bucket index for the
+    /// first span.
+    pub offset: i32,
+    /// The number of consecutive buckets in the span.
+    pub length: u32,
+}
+
+/// A Prometheus native histogram with only non-negative observations.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NativeHistogram {
+    /// The resolution of the exponential buckets. Bucket boundaries are powers
+    /// of `2^(2^-schema)`.
+    pub schema: i32,
+    /// Observations with a value at or below this threshold are counted in
+    /// the zero bucket.
+    pub zero_threshold: f64,
+    /// The number of observations in the zero bucket.
+    pub zero_count: u64,
+    /// The total number of observations.
+    pub count: u64,
+    /// The approximate sum of observations, using the midpoint of each bucket.
+    pub sum: f64,
+    /// The spans of populated positive buckets.
+    pub positive_spans: Vec<BucketSpan>,
+    /// The counts of the positive buckets, each as a delta from the previous
+    /// bucket count.
+    pub positive_deltas: Vec<i64>,
+}
+
+/// Returns the native histogram schema with bucket widths closest to those of
+/// the provided [`crate::Config`]. This is the grouping power, capped at the
+/// finest schema Prometheus supports.
+///
+/// An error is returned for a [`Config::logarithmic`] configuration, whose
+/// buckets are not subdivisions of powers of two.
+pub fn schema(config: &Config) -> core::result::Result<i32, Error> {
+    if config.relative_accuracy().is_some() {
+        return Err(Error::IncompatibleParameters);
+    }
+
+    Ok(config.grouping_power().min(MAX_SCHEMA) as i32)
+}
+
+impl NativeHistogram {
+    /// Builds a native histogram from a collection of buckets.
+    ///
+    /// Native buckets are exponential while the buckets of this crate linearly
+    /// subdivide each power of two, so each bucket is assigned to the native
+    /// bucket which contains its inclusive upper bound. The result is an
+    /// approximation within the width of a native bucket.
+    fn from_buckets(
+        config: &Config,
+        buckets: impl IntoIterator<Item = Bucket>,
+    ) -> core::result::Result<Self, Error> {
+        let schema = schema(config)?;
+
+        let mut histogram = Self {
+            schema,
+            zero_threshold: 0.0,
+            zero_count: 0,
+            count: 0,
+            sum: 0.0,
+            positive_spans: Vec::new(),
+            positive_deltas: Vec::new(),
+        };
+
+        // the index and count of the native bucket currently being aggregated
+        let mut current: Option<(i32, u64)> = None;
+        let mut previous_index: i32 = 0;
+        let mut previous_count: u64 = 0;
+
+        let mut push = |histogram: &mut Self, index: i32, count: u64| {
+            let gap = index - previous_index - 1;
+
+            match histogram.positive_spans.last_mut() {
+                Some(span) if (gap as u32) <= MAX_SPAN_GAP => {
+                    for _ in 0..gap {
+                        histogram.positive_deltas.push(-(previous_count as i64));
+                        previous_count = 0;
+                    }
+                    span.length += gap as u32 + 1;
+                }
+                Some(_) => {
+                    histogram.positive_spans.push(BucketSpan {
+                        offset: gap,
+                        length: 1,
+                    });
+                }
+                None => {
+                    histogram.positive_spans.push(BucketSpan {
+                        offset: index,
+                        length: 1,
+                    });
+                }
+            }
+
+            histogram
+                .positive_deltas
+                .push(count as i64 - previous_count as i64);
+
+            previous_index = index;
+            previous_count = count;
+        };
+
+        for bucket in buckets {
+            if bucket.count() == 0 {
+                continue;
+            }
+
+            histogram.count = histogram.count.wrapping_add(bucket.count());
+            histogram.sum += midpoint(&bucket) * bucket.count() as f64;
+
+            if bucket.end() == 0 {
+                histogram.zero_count = histogram.zero_count.wrapping_add(bucket.count());
+                continue;
+            }
+
+            let index = native_index(bucket.end(), schema);
+
+            current = match current {
+                Some((i, count)) if i == index => Some((i, count.wrapping_add(bucket.count()))),
+                Some((i, count)) => {
+                    push(&mut histogram, i, count);
+                    Some((index, bucket.count()))
+                }
+                None => Some((index, bucket.count())),
+            };
+        }
+
+        if let Some((i, count)) = current {
+            push(&mut histogram, i, count);
+        }
+
+        Ok(histogram)
+    }
+}
+
+/// The conversion fails for a [`Config::logarithmic`] configuration, see
+/// [`schema`].
+impl TryFrom<&Histogram> for NativeHistogram {
+    type Error = Error;
+
+    fn try_from(histogram: &Histogram) -> core::result::Result<Self, Error> {
+        Self::from_buckets(&histogram.config(), histogram)
+    }
+}
+
+/// The conversion fails for a [`Config::logarithmic`] configuration, see
+/// [`schema`].
+impl TryFrom<&SparseHistogram> for NativeHistogram {
+    type Error = Error;
+
+    fn try_from(histogram: &SparseHistogram) -> core::result::Result<Self, Error> {
+        Self::from_buckets(&histogram.config, histogram)
+    }
+}
+
+/// Writes a collection of buckets, such as a [`crate::Histogram`] or
+/// [`crate::SparseHistogram`], as a classic histogram metric family in the
+/// OpenMetrics text format. The caller is responsible for terminating the
+/// exposition with `# EOF`.
+///
+/// Since each bucket's upper bound is inclusive, buckets map exactly onto `le`
+/// boundaries. Only non-empty buckets are written, followed by the `+Inf`
+/// bucket, `_count`, and the approximate `_sum` which uses the midpoint of each
+/// bucket.
+pub fn write_openmetrics<W: Write>(
+    w: &mut W,
+    name: &str,
+    labels: &[(&str, &str)],
+    buckets: impl IntoIterator<Item = Bucket>,
+) -> Result {
+    let mut labelset = String::new();
+
+    for (key, value) in labels {
+        labelset.push_str(key);
+        labelset.push_str("=\"");
+        for c in value.chars() {
+            match c {
+                '\\' => labelset.push_str("\\\\"),
+                '"' => labelset.push_str("\\\""),
+                '\n' => labelset.push_str("\\n"),
+                c => labelset.push(c),
+            }
+        }
+        labelset.push_str("\",");
+    }
+
+    writeln!(w, "# TYPE {name} histogram")?;
+
+    let mut count: u64 = 0;
+    let mut sum: f64 = 0.0;
+
+    for bucket in buckets {
+        if bucket.count() == 0 {
+            continue;
+        }
+
+        count = count.wrapping_add(bucket.count());
+        sum += midpoint(&bucket) * bucket.count() as f64;
+
+        writeln!(
+            w,
+            "{name}_bucket{{{labelset}le=\"{}.0\"}} {count}",
+            bucket.end()
+        )?;
+    }
+
+    writeln!(w, "{name}_bucket{{{labelset}le=\"+Inf\"}} {count}")?;
+
+    let labelset = labelset.trim_end_matches(',');
+
+    if labelset.is_empty() {
+        writeln!(w, "{name}_count {count}")?;
+        writeln!(w, "{name}_sum {sum:?}")
+    } else {
+        writeln!(w, "{name}_count{{{labelset}}} {count}")?;
+        writeln!(w, "{name}_sum{{{labelset}}} {sum:?}")
+    }
+}
+
+/// Returns the midpoint of the bucket's range.
+fn midpoint(bucket: &Bucket) -> f64 {
+    (bucket.start() as f64 + bucket.end() as f64) / 2.0
+}
+
+/// The native bucket boundaries within a power of two for the finest schema,
+/// `floor(2^(k / 256) * 2^63)` for each `k` in `0..256`. The boundaries of a
+/// coarser schema are every `2^(8 - schema)`th entry.
+///
+/// Other than the first, the boundaries are irrational, so a value whose most
+/// significant bit has been shifted to bit 63 is above a boundary exactly when
+/// it is above the truncated entry.
+#[rustfmt::skip]
+const BOUNDARIES: [u64; 1 << MAX_SCHEMA] = [
+    0x8000000000000000, 0x8058d7d2d5e5f6b0, 0x80b1ed4fd999ab6c, 0x810b40a1d81406d4,
+    0x8164d1f3bc030773, 0x81bea1708dde6055, 0x8218af4373fc25eb, 0x8272fb97b2a5894c,
+    0x82cd8698ac2ba1d7, 0x83285071e0fc4546, 0x8383594eefb6ee36, 0x83dea15b9541b132,
+    0x843a28c3acde4046, 0x8495efb3303efd2f, 0x84f1f656379c1a29, 0x854e3cd8f9c8c95d,
+    0x85aac367cc487b14, 0x86078a2f23642a9f, 0x8664915b923fba03, 0x86c1d919caef5c87,
+    0x871f61969e8d1010, 0x877d2afefd4e256c, 0x87db357ff698d791, 0x88398146b919f1d4,
+    0x88980e8092da8527, 0x88f6dd5af155ac6b, 0x8955ee03618e5fdc, 0x89b540a7902557a3,
+    0x8a14d575496efd9a, 0x8a74ac9a79896e46, 0x8ad4c6452c728924, 0x8b3522a38e1e1031,
+    0x8b95c1e3ea8bd6e6, 0x8bf6a434adde0084, 0x8c57c9c4646f4ddd, 0x8cb932c1bae97a95,
+    0x8d1adf5b7e5ba9e5, 0x8d7ccfc09c50e2f7, 0x8ddf042022e69cd5, 0x8e417ca940e35a01,
+    0x8ea4398b45cd53c0, 0x8f073af5a2013520, 0x8f6a8117e6c8e5c4, 0x8fce0c21c6726481,
+    0x9031dc431466b1dc, 0x9095f1abc540ca6b, 0x90fa4c8beee4b12a, 0x915eed13c89689d3,
+    0x91c3d373ab11c336, 0x9228ffdc10a051ac, 0x928e727d9531f9ac, 0x92f42b88f673aa7c,
+    0x935a2b2f13e6e92b, 0x93c071a0eef94bc0, 0x9426ff0fab1c04b6, 0x948dd3ac8ddb7ed3,
+    0x94f4efa8fef70961, 0x955c5336887894d5, 0x95c3fe86d6cc7fee, 0x962bf1cbb8d9755f,
+    0x96942d3720185a00, 0x96fcb0fb20ac4ba2, 0x97657d49f17ab08e, 0x97ce9255ec4357ab,
+    0x9837f0518db8a96f, 0x98a1976f7597e995, 0x990b87e266c189a9, 0x9975c1dd47518c77,
+    0x99e0459320b7fa64, 0x9a4b13371fd166ca, 0x9ab62afc94ff864a, 0x9b218d16f441d63c,
+    0x9b8d39b9d54e5538, 0x9bf93118f3aa4cc1, 0x9c6573682ec32c2d, 0x9cd200db8a0774ca,
+    0x9d3ed9a72cffb750, 0x9dabfdff6367a2a9, 0x9e196e189d472420, 0x9e872a276f0b98ff,
+    0x9ef5326091a111ad, 0x9f6386f8e28ba650, 0x9fd228256400dd05, 0xa041161b3d0121bd,
+    0xa0b0510fb9714fc2, 0xa11fd9384a344cf7, 0xa18faeca8544b6e3, 0xa1ffd1fc25cea188,
+    0xa27043030c496818, 0xa2e102153e918f9e, 0xa3520f68e802bb92, 0xa3c36b345991b47b,
+    0xa43515ae09e6809e, 0xa4a70f0c95768ec4, 0xa5195786be9ef339, 0xa58bef536dbeb6ed,
+    0xa5fed6a9b15138ea, 0xa6720dc0be08a20b, 0xa6e594cfeee86b1d, 0xa7596c0ec55ff55b,
+    0xa7cd93b4e9653569, 0xa8420bfa298f70d1, 0xa8b6d5167b320e08, 0xa92bef41fa77771b,
+    0xa9a15ab4ea7c0ef8, 0xaa1717a7b5693979, 0xaa8d2652ec907629, 0xab0386ef48868de0,
+    0xab7a39b5a93ed337, 0xabf13edf162675e8, 0xac6896a4be3fe929, 0xace0413ff83e5d03,
+    0xad583eea42a14ac6, 0xadd08fdd43d01491, 0xae493452ca35b80e, 0xaec22c84cc5c9465,
+    0xaf3b78ad690a4374, 0xafb51906e75b8661, 0xb02f0dcbb6e04583, 0xb0a957366fb7a3c9,
+    0xb123f581d2ac258f, 0xb19ee8e8c94feb08, 0xb21a31a66618fe3b, 0xb295cff5e47db4a3,
+    0xb311c412a9112489, 0xb38e0e38419fae17, 0xb40aaea2654b9840, 0xb487a58cf4a9c180,
+    0xb504f333f9de6484, 0xb58297d3a8b9f0d1, 0xb60093a85ed5f76b, 0xb67ee6eea3b22b8f,
+    0xb6fd91e328d17791, 0xb77c94c2c9d725e8, 0xb7fbefca8ca41e7c, 0xb87ba337a1743833,
+    0xb8fbaf4762fb9ee9, 0xb97c143756844dbe, 0xb9fcd2452c0b9dea, 0xba7de9aebe5fea08,
+    0xbaff5ab2133e45fb, 0xbb81258d5b704b6f, 0xbc034a7ef2e9fb0c, 0xbc85c9c560e7b269,
+    0xbd08a39f580c36be, 0xbd8bd84bb67ed482, 0xbe0f6809860993e2, 0xbe935317fc378237,
+    0xbf1799b67a731082, 0xbf9c3c248e2486f8, 0xc0213aa1f0d08db0, 0xc0a6956e8836ca8c,
+    0xc12c4cca66709456, 0xc1b260f5ca0fbb33, 0xc238d2311e3d6672, 0xc2bfa0bcfad907c8,
+    0xc346ccda24976407, 0xc3ce56c98d21b15d, 0xc4563ecc5334cb32, 0xc4de8523c2c07baa,
+    0xc5672a115506dadd, 0xc5f02dd6b0bbc3d9, 0xc67990b5aa245f79, 0xc70352f04336c51d,
+    0xc78d74c8abb9b15c, 0xc817f681416452b2, 0xc8a2d85c8ffe2c45, 0xc92e1a9d517f0ecb,
+    0xc9b9bd866e2f27a2, 0xca45c15afcc72623, 0xcad2265e4290774d, 0xcb5eecd3b38597c8,
+    0xcbec14fef2727c5c, 0xcc799f23d11510e5, 0xcd078b86503dcdd1, 0xcd95da6a9ff06444,
+    0xce248c151f8480e3, 0xceb3a0ca5dc6a55d, 0xcf4318cf191918c1, 0xcfd2f4683f94eeb5,
+    0xd06333daef2b2594, 0xd0f3d76c75c5db8c, 0xd184df6251699ac6, 0xd2164c023056bcab,
+    0xd2a81d91f12ae45a, 0xd33a5457a3029054, 0xd3ccf099859ac379, 0xd45ff29e0972c560,
+    0xd4f35aabcfedfa1f, 0xd5872909ab75d189, 0xd61b5dfe9f9bce06, 0xd6aff9d1e13ba2fd,
+    0xd744fccad69d6af4, 0xd7da67311797f569, 0xd870394c6db32c84, 0xd9067364d44a929b,
+    0xd99d15c278afd7b5, 0xda3420adba4d8704, 0xdacb946f2ac9cc71, 0xdb63714f8e295255,
+    0xdbfbb797daf23755, 0xdc9467913a4f1c91, 0xdd2d818508324c20, 0xddc705bcd378f7f0,
+    0xde60f4825e0e9123, 0xdefb4e1f9d1037f1, 0xdf9612deb8f04420, 0xe031430a0d99e627,
+    0xe0ccdeec2a94e111, 0xe168e6cfd3295d23, 0xe2055afffe83d368, 0xe2a23bc7d7d91225,
+    0xe33f8972be8a5a51, 0xe3dd444c46499618, 0xe47b6ca0373da88d, 0xe51a02ba8e26d680,
+    0xe5b906e77c8348a8, 0xe658797368b3a716, 0xe6f85aaaee1fce22, 0xe798aadadd5b9cbe,
+    0xe8396a503c4bdc68, 0xe8da9958464b42aa, 0xe97c38406c4f8c56, 0xea1e4756550eb27b,
+    0xeac0c6e7dd24392e, 0xeb63b7431736983f, 0xec0718b64c1cbddc, 0xecaaeb8ffb03ab40,
+    0xed4f301ed9942b84, 0xedf3e6b1d418a491, 0xee990f980da3025b, 0xef3eab20e032bc6b,
+    0xefe4b99bdcdaf5cb, 0xf08b3b58cbe8b76a, 0xf13230a7ad094509, 0xf1d999d8b7708cc1,
+    0xf281773c59ffb139, 0xf329c9233b6bae9c, 0xf3d28fde3a641a5a, 0xf47bcbbe6db9fdde,
+    0xf5257d152486cc2c, 0xf5cfa433e6537290, 0xf67a416c733f846d, 0xf7255510c4288238,
+    0xf7d0df730ad13bb8, 0xf87ce0e5b2094d9b, 0xf92959bb5dd4ba74, 0xf9d64a46eb939f35,
+    0xfa83b2db722a033a, 0xfb3193cc4227c3f4, 0xfbdfed6ce5f09c48, 0xfc8ec01121e447bb,
+    0xfd3e0c0cf486c174, 0xfdedd1b496a89f34, 0xfe9e115c7b8f884b, 0xff4ecb59511ec8a5,
+];
+
+/// Returns the index of the native bucket with the given schema which contains
+/// the value. Native bucket `i` covers `(base^(i-1), base^i]`.
+///
+/// This uses integer math, since a float cannot represent every value above
+/// `2^53`.
+fn native_index(value: u64, schema: i32) -> i32 {
+    let power = 63 - value.leading_zeros() as i32;
+    let mantissa = value << value.leading_zeros();
+
+    // powers of two are the upper bound of a bucket
+    if mantissa == 1 << 63 {
+        return power << schema;
+    }
+
+    // the number of boundaries of the finest schema within this power of two
+    // which are below the value, scaled down to the provided schema
+    let below = BOUNDARIES.partition_point(|boundary| *boundary < mantissa) - 1;
+
+    (power << schema) + (below >> (MAX_SCHEMA as i32 - schema)) as i32 + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    // Test bucket index calculations against known boundaries
+    fn native_index() {
+        assert_eq!(super::native_index(1, 0), 0);
+        assert_eq!(super::native_index(2, 0), 1);
+        assert_eq!(super::native_index(3, 0), 2);
+        assert_eq!(super::native_index(4, 0), 2);
+        assert_eq!(super::native_index(5, 0), 3);
+        assert_eq!(super::native_index(2, 3), 8);
+        assert_eq!(super::native_index(u64::MAX, 0), 64);
+        assert_eq!(super::native_index(u64::MAX, 8), 64 * 256);
+
+        // values above 2^53 cannot all be represented as a float
+        assert_eq!(super::native_index(1 << 53, 8), 53 * 256);
+        assert_eq!(super::native_index((1 << 53) + 1, 8), 53 * 256 + 1);
+        assert_eq!(super::native_index(1 << 63, 0), 63);
+        assert_eq!(super::native_index((1 << 63) + 1, 0), 64);
+
+        // either side of 2^(54 + 1/256)
+        assert_eq!(super::native_index(18063240498705147, 8), 54 * 256 + 1);
+        assert_eq!(super::native_index(18063240498705148, 8), 54 * 256 + 2);
+
+        // either side of 2^60.5
+        assert_eq!(super::native_index(1630477228166597776, 1), 121);
+        assert_eq!(super::native_index(1630477228166597777, 1), 122);
+    }
+
+    #[test]
+    // Test that bucket indices match the logarithm where it is precise
+    fn native_index_log2() {
+        for schema in 0..=MAX_SCHEMA as i32 {
+            for value in 1..(1 << 16) {
+                assert_eq!(
+                    super::native_index(value, schema),
+                    ((value as f64).log2() * (1 << schema) as f64).ceil() as i32,
+                    "value: {value} schema: {schema}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    // Test conversion into spans and deltas
+    fn native() {
+        let mut histogram = Histogram::new(0, 8).unwrap();
+        histogram.add(0, 3).unwrap();
+        histogram.add(1, 2).unwrap();
+        histogram.add(2, 5).unwrap();
+        histogram.add(40, 1).unwrap();
+
+        let native = NativeHistogram::try_from(&histogram).unwrap();
+
+        assert_eq!(native.schema, 0);
+        assert_eq!(native.zero_count, 3);
+        assert_eq!(native.count, 11);
+        assert_eq!(native.sum, 2.0 + 12.5 + 47.5);
+        assert_eq!(
+            native.positive_spans,
+            vec![
+                BucketSpan {
+                    offset: 0,
+                    length: 3
+                },
+                BucketSpan {
+                    offset: 3,
+                    length: 1
+                },
+            ]
+        );
+        assert_eq!(native.positive_deltas, vec![2, -2, 5, -4]);
+
+        let sparse = SparseHistogram::from(&histogram);
+        assert_eq!(NativeHistogram::try_from(&sparse), Ok(native));
+
+        let native = NativeHistogram::try_from(&Histogram::new(12, 64).unwrap()).unwrap();
+        assert_eq!(native.schema, 8);
+        assert_eq!(native.count, 0);
+        assert!(native.positive_spans.is_empty());
+
+        let config = Config::logarithmic(0.01, 64).unwrap();
+        assert_eq!(schema(&config), Err(Error::IncompatibleParameters));
+        assert_eq!(
+            NativeHistogram::try_from(&Histogram::with_config(&config)),
+            Err(Error::IncompatibleParameters)
+        );
+    }
+
+    #[test]
+    // Test the classic text format
+    fn openmetrics() {
+        let mut histogram = Histogram::new(2, 8).unwrap();
+        histogram.add(1, 2).unwrap();
+        histogram.add(10, 1).unwrap();
+
+        let mut out = String::new();
+        write_openmetrics(&mut out, "latency", &[], &histogram).unwrap();
+        assert_eq!(
+            out,
+            "# TYPE latency histogram\n\
+             latency_bucket{le=\"1.0\"} 2\n\
+             latency_bucket{le=\"11.0\"} 3\n\
+             latency_bucket{le=\"+Inf\"} 3\n\
+             latency_count 3\n\
+             latency_sum 12.5\n"
+        );
+
+        let mut out = String::new();
+        write_openmetrics(
+            &mut out,
+            "latency",
+            &[("op", "get"), ("path", "a\"b")],
+            &SparseHistogram::from(&histogram),
+        )
+        .unwrap();
+        assert_eq!(
+            out,
+            "# TYPE latency histogram\n\
+             latency_bucket{op=\"get\",path=\"a\\\"b\",le=\"1.0\"} 2\n\
+             latency_bucket{op=\"get\",path=\"a\\\"b\",le=\"11.0\"} 3\n\
+             latency_bucket{op=\"get\",path=\"a\\\"b\",le=\"+Inf\"} 3\n\
+             latency_count{op=\"get\",path=\"a\\\"b\"} 3\n\
+             latency_sum{op=\"get\",path=\"a\\\"b\"} 12.5\n"
+        );
+    }
+}
@@ -0,0 +1,245 @@
+//! Property-based tests which check invariants that should hold for every
+//! configuration, rather than for a few fixed cases.
+
+use crate::{Config, Error, Histogram, SparseHistogram};
+use proptest::prelude::*;
+
+/// The largest grouping power used for strategies which allocate histograms,
+/// which keeps each case fast.
+const MAX_GROUPING_POWER: u8 = 10;
+
+/// Any standard configuration.
+fn standard_config(max_grouping_power: u8) -> impl Strategy<Value = Config> {
+    (1_u8..=64)
+        .prop_flat_map(move |n| (0..n.min(max_grouping_power + 1), Just(n)))
+        .prop_map(|(grouping_power, max_value_power)| {
+            Config::new(grouping_power, max_value_power).unwrap()
+        })
+}
+
+/// Any logarithmic configuration with a relative accuracy of at least 0.1%.
+fn logarithmic_config() -> impl Strategy<Value = Config> {
+    (0.001_f64..0.5, 1_u8..=64).prop_map(|(accuracy, max_value_power)| {
+        Config::logarithmic(accuracy, max_value_power).unwrap()
+    })
+}
+
+/// Any configuration, favoring standard configurations.
+fn config(max_grouping_power: u8) -> impl Strategy<Value = Config> {
+    prop_oneof![
+        3 => standard_config(max_grouping_power),
+        1 => logarithmic_config(),
+    ]
+}
+
+/// Returns the largest value which can be stored.
+fn max(config: &Config) -> u64 {
+    config.index_to_upper_bound(config.total_buckets() - 1)
+}
+
+/// Values spread evenly across orders of magnitude, so small values are as
+/// likely as large ones.
+fn value() -> impl Strategy<Value = u64> {
+    (any::<u64>(), 0_u32..64).prop_map(|(value, shift)| value >> shift)
+}
+
+/// Values with counts, where the counts are occasionally large enough to
+/// overflow when combined.
+fn values() -> impl Strategy<Value = Vec<(u64, u64)>> {
+    prop::collection::vec(
+        (value(), prop_oneof![9 => 1_u64..1000, 1 => any::<u64>()]),
+        0..64,
+    )
+}
+
+/// Builds a histogram, wrapping any overflowing counts.
+fn histogram(config: &Config, values: &[(u64, u64)]) -> Histogram {
+    let mut histogram = Histogram::with_config(config);
+
+    for (value, count) in values {
+        let index = config.value_to_index(value & max(config)).unwrap();
+        histogram.buckets[index] = histogram.buckets[index].wrapping_add(*count);
+    }
+
+    histogram
+}
+
+/// Returns the value at the percentile of the sorted values, using the same
+/// rank as the histograms, which is the first observation for `0.0`.
+fn exact_percentile(sorted: &[u64], percentile: f64) -> u64 {
+    let rank = (percentile / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+proptest! {
+    #[test]
+    // Every value maps to a bucket whose range contains it and the buckets
+    // around it are contiguous
+    fn index_round_trip(
+        config in config(MAX_GROUPING_POWER + 4),
+        values in prop::collection::vec(value(), 1..32),
+    ) {
+        let max = max(&config);
+        let last = config.total_buckets() - 1;
+
+        prop_assert_eq!(config.index_to_lower_bound(0), 0);
+        prop_assert_eq!(config.value_to_index(max), Ok(last));
+
+        if max < u64::MAX {
+            prop_assert_eq!(config.value_to_index(max + 1), Err(Error::OutOfRange));
+        }
+
+        for value in values {
+            let value = value & max;
+            let index = config.value_to_index(value).unwrap();
+            let range = config.index_to_range(index);
+
+            prop_assert!(range.contains(&value));
+            prop_assert_eq!(config.value_to_index(*range.start()), Ok(index));
+            prop_assert_eq!(config.value_to_index(*range.end()), Ok(index));
+
+            if index > 0 {
+                prop_assert_eq!(config.index_to_upper_bound(index - 1) + 1, *range.start());
+            }
+            if index < last {
+                prop_assert_eq!(config.index_to_lower_bound(index + 1), range.end() + 1);
+            }
+        }
+    }
+
+    #[test]
+    // Downsampling matches recording into the smaller configuration directly
+    // and preserves the total count
+    fn downsample(
+        config in standard_config(MAX_GROUPING_POWER),
+        values in values(),
+        reduction in 1_u8..8,
+    ) {
+        let histogram = histogram(&config, &values);
+        let sparse = SparseHistogram::from(&histogram);
+
+        let grouping_power = config.grouping_power().saturating_sub(reduction);
+        let result = histogram.downsample(grouping_power);
+
+        if grouping_power == config.grouping_power() {
+            prop_assert_eq!(result, Err(Error::MaxPowerTooLow));
+            return Ok(());
+        }
+
+        let downsampled = result.unwrap();
+        let expected = self::histogram(
+            &Config::new(grouping_power, config.max_value_power()).unwrap(),
+            &values,
+        );
+
+        let total = |histogram: &Histogram| -> u64 {
+            histogram.as_slice().iter().fold(0, |total, count| total.wrapping_add(*count))
+        };
+
+        prop_assert_eq!(total(&downsampled), total(&histogram));
+        prop_assert_eq!(&downsampled, &expected);
+        prop_assert_eq!(
+            sparse.downsample(grouping_power).unwrap(),
+            SparseHistogram::from(&expected)
+        );
+    }
+
+    #[test]
+    // Arithmetic on sparse histograms agrees with the dense histograms
+    fn arithmetic(config in config(MAX_GROUPING_POWER), a in values(), b in values()) {
+        let a = histogram(&config, &a);
+        let b = histogram(&config, &b);
+        let sparse_a = SparseHistogram::from(&a);
+        let sparse_b = SparseHistogram::from(&b);
+
+        let sparse = |result: Result<Histogram, Error>| {
+            result.map(|histogram| SparseHistogram::from(&histogram))
+        };
+
+        prop_assert_eq!(sparse_a.checked_add(&sparse_b), sparse(a.checked_add(&b)));
+        prop_assert_eq!(sparse_a.wrapping_add(&sparse_b), sparse(a.wrapping_add(&b)));
+        prop_assert_eq!(sparse_a.wrapping_sub(&sparse_b), sparse(a.wrapping_sub(&b)));
+
+        // subtract a histogram which has at most the counts of the first
+        let mut subset = a.clone();
+        for (count, other) in subset.buckets.iter_mut().zip(b.as_slice()) {
+            *count = (*count).min(*other);
+        }
+        let sparse_subset = SparseHistogram::from(&subset);
+
+        let difference = a.checked_sub(&subset).unwrap();
+        prop_assert_eq!(
+            sparse_a.checked_sub(&sparse_subset),
+            Ok(SparseHistogram::from(&difference))
+        );
+        prop_assert_eq!(difference.checked_add(&subset), Ok(a));
+    }
+
+    #[test]
+    // The bucket for each percentile contains the exact value from the sorted
+    // data and its midpoint is within the configured error of it
+    fn percentile_error(
+        config in config(MAX_GROUPING_POWER),
+        values in prop::collection::vec(value(), 1..256),
+        percentiles in prop::collection::vec(0.0_f64..=100.0, 1..8),
+    ) {
+        let values: Vec<u64> = values.iter().map(|value| value & max(&config)).collect();
+
+        let mut histogram = Histogram::with_config(&config);
+        for value in &values {
+            histogram.increment(*value).unwrap();
+        }
+
+        let mut sorted = values.clone();
+        sorted.sort_unstable();
+
+        let error = config.error() / 100.0;
+
+        for (percentile, bucket) in histogram.percentiles(&percentiles).unwrap().unwrap() {
+            let exact = exact_percentile(&sorted, percentile);
+            prop_assert!(bucket.range().contains(&exact));
+
+            let midpoint = bucket.start() as f64 / 2.0 + bucket.end() as f64 / 2.0;
+            // allow for rounding in the floating point boundaries
+            prop_assert!((midpoint - exact as f64).abs() <= midpoint * error * (1.0 + 1e-9));
+        }
+    }
+}
+
+#[test]
+// Every valid standard configuration has contiguous buckets which cover the
+// whole range of values
+fn every_config() {
+    for max_value_power in 1..=64 {
+        for grouping_power in 0..max_value_power {
+            let config = match Config::new(grouping_power, max_value_power) {
+                Ok(config) => config,
+                Err(e) => {
+                    // only rejected if there are too many buckets
+                    assert_eq!(e, Error::IncompatibleParameters);
+                    assert!(grouping_power > 26);
+                    continue;
+                }
+            };
+            let total = config.total_buckets();
+
+            // check every bucket of small configurations and a sample of the
+            // buckets of large ones
+            let step = (total / 4096).max(1);
+
+            for index in (0..total).step_by(step).chain([total - 1]) {
+                let range = config.index_to_range(index);
+
+                assert_eq!(config.value_to_index(*range.start()), Ok(index));
+                assert_eq!(config.value_to_index(*range.end()), Ok(index));
+
+                if index + 1 < total {
+                    assert_eq!(config.index_to_lower_bound(index + 1), range.end() + 1);
+                }
+            }
+
+            assert_eq!(config.index_to_upper_bound(total - 1), max(&config));
+            assert_eq!(max(&config), u64::MAX >> (64 - max_value_power));
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use crate::{Config, Error};
+
+/// How to handle recorded values which are above the maximum value of the
+/// configuration a histogram is being re-bucketed into.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// Fail with [`Error::OutOfRange`] if any observation would be lost.
+    #[default]
+    Error,
+    /// Count the observations in the highest bucket of the new configuration.
+    Clamp,
+    /// Drop the observations.
+    Discard,
+}
+
+/// Describes how the observations of a histogram were carried over when it was
+/// re-bucketed into a new configuration.
+///
+/// Every observation is counted exactly once: it was either mapped into a
+/// single new bucket, spread proportionally across several new buckets, or
+/// was beyond the new maximum value and was clamped or discarded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rebucketing {
+    exact: u128,
+    approximated: u128,
+    clamped: u128,
+    discarded: u128,
+}
+
+impl Rebucketing {
+    /// Returns the number of observations whose bucket fell entirely within a
+    /// single new bucket. These observations are unaffected by re-bucketing.
+    pub fn exact(&self) -> u128 {
+        self.exact
+    }
+
+    /// Returns the number of observations whose bucket spanned several new
+    /// buckets and which were spread across them in proportion to the width
+    /// of each overlap.
+    pub fn approximated(&self) -> u128 {
+        self.approximated
+    }
+
+    /// Returns the number of observations above the new maximum value which
+    /// were moved into the highest new bucket.
+    pub fn clamped(&self) -> u128 {
+        self.clamped
+    }
+
+    /// Returns the number of observations above the new maximum value which
+    /// were dropped.
+    pub fn discarded(&self) -> u128 {
+        self.discarded
+    }
+
+    /// Returns true if no observations were clamped or discarded.
+    pub fn is_lossless(&self) -> bool {
+        self.clamped == 0 && self.discarded == 0
+    }
+}
+
+/// Maps the non-zero buckets, which must be in increasing order of index, from
+/// one configuration to another. Each resulting bucket is passed to `emit` in
+/// non-decreasing order of index.
+///
+/// A bucket which overlaps several new buckets is divided between them by the
+/// width of each overlap, assuming that observations are uniformly
+/// distributed within the bucket. Cumulative rounding is used so that no
+/// observations are gained or lost.
+pub(crate) fn rebucket(
+    from: &Config,
+    to: &Config,
+    buckets: impl Iterator<Item = (usize, u64)>,
+    overflow: Overflow,
+    mut emit: impl FnMut(usize, u64),
+) -> Result<Rebucketing, Error> {
+    let mut report = Rebucketing::default();
+    let last = to.total_buckets() - 1;
+
+    for (index, count) in buckets.filter(|(_, count)| *count != 0) {
+        let lower = from.index_to_lower_bound(index);
+        let upper = from.index_to_upper_bound(index);
+        let width = (upper - lower) as u128 + 1;
+
+        let mut covered = 0;
+        let mut assigned = 0;
+        let mut spread = 0;
+
+        if let Ok(mut target) = to.value_to_index(lower) {
+            while target <= last && to.index_to_lower_bound(target) <= upper {
+                let overlap = to.index_to_upper_bound(target).min(upper)
+                    - to.index_to_lower_bound(target).max(lower);
+
+                covered += overlap as u128 + 1;
+                let total = (count as u128 * covered / width) as u64;
+
+                emit(target, total - assigned);
+                assigned = total;
+                spread += 1;
+                target += 1;
+            }
+        }
+
+        match spread {
+            0 => {}
+            1 => report.exact += assigned as u128,
+            _ => report.approximated += assigned as u128,
+        }
+
+        let remaining = count - assigned;
+
+        if remaining != 0 {
+            match overflow {
+                Overflow::Error => return Err(Error::OutOfRange),
+                Overflow::Clamp => {
+                    emit(last, remaining);
+                    report.clamped += remaining as u128;
+                }
+                Overflow::Discard => report.discarded += remaining as u128,
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(
+        from: &Config,
+        to: &Config,
+        buckets: &[(usize, u64)],
+        overflow: Overflow,
+    ) -> Result<(Vec<(usize, u64)>, Rebucketing), Error> {
+        let mut result: Vec<(usize, u64)> = Vec::new();
+        let report = rebucket(from, to, buckets.iter().copied(), overflow, |i, n| {
+            if n != 0 {
+                result.push((i, n))
+            }
+        })?;
+        Ok((result, report))
+    }
+
+    #[test]
+    // Tests that counts are spread into finer buckets by overlap
+    fn spread() {
+        let from = Config::new(2, 16).unwrap();
+        let to = Config::new(4, 16).unwrap();
+
+        // 48..=55 is split into four buckets two values wide
+        let index = from.value_to_index(48).unwrap();
+        assert_eq!(from.index_to_range(index), 48..=55);
+
+        let (buckets, report) = run(&from, &to, &[(index, 10)], Overflow::Error).unwrap();
+        assert_eq!(buckets.iter().map(|(_, n)| n).sum::<u64>(), 10);
+        assert_eq!(buckets.len(), 4);
+        for (i, _) in &buckets {
+            assert!(to.index_to_lower_bound(*i) >= 48 && to.index_to_upper_bound(*i) <= 55);
+        }
+        assert_eq!(report.approximated(), 10);
+        assert_eq!(report.exact(), 0);
+
+        // values below the cutoff map exactly
+        let (buckets, report) = run(&from, &to, &[(3, 7)], Overflow::Error).unwrap();
+        assert_eq!(buckets, vec![(3, 7)]);
+        assert_eq!(report.exact(), 7);
+    }
+
+    #[test]
+    // Tests each overflow policy
+    fn overflow() {
+        let from = Config::new(2, 16).unwrap();
+        let to = Config::new(2, 8).unwrap();
+        let last = to.total_buckets() - 1;
+        let beyond = from.value_to_index(1000).unwrap();
+
+        assert_eq!(
+            run(&from, &to, &[(1, 1), (beyond, 3)], Overflow::Error),
+            Err(Error::OutOfRange)
+        );
+
+        let (buckets, report) = run(&from, &to, &[(1, 1), (beyond, 3)], Overflow::Clamp).unwrap();
+        assert_eq!(buckets, vec![(1, 1), (last, 3)]);
+        assert_eq!(report.clamped(), 3);
+        assert!(!report.is_lossless());
+
+        let (buckets, report) = run(&from, &to, &[(1, 1), (beyond, 3)], Overflow::Discard).unwrap();
+        assert_eq!(buckets, vec![(1, 1)]);
+        assert_eq!(report.discarded(), 3);
+    }
+}
@@ -0,0 +1,186 @@
+use crate::{Config, Error, Histogram};
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// The number of counters in a single padded line. Two cache lines are used
+/// since adjacent lines are often prefetched together.
+const LINE_COUNTERS: usize = 16;
+
+/// A group of counters which is aligned so that no two shards ever share a
+/// cache line.
+#[repr(align(128))]
+struct Line([AtomicU64; LINE_COUNTERS]);
+
+impl Line {
+    fn new() -> Self {
+        Self(core::array::from_fn(|_| AtomicU64::new(0)))
+    }
+}
+
+/// Source of shard assignments for threads.
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// The shard assignment for the current thread.
+    static SHARD: usize = NEXT_SHARD.fetch_add(1, Ordering::Relaxed);
+}
+
+/// A histogram that spreads its atomic 64bit counters across several
+/// independent shards to reduce contention.
+///
+/// With a plain [`crate::AtomicHistogram`], threads which record similar
+/// values all update the same counters and contend for the same cache lines.
+/// Here, each thread is assigned a shard in round-robin order and only updates
+/// the counters in that shard. Each shard is padded to whole cache lines.
+///
+/// As with the atomic histogram, a snapshot must be taken to report
+/// percentiles. Snapshots merge the counters from all shards, so they are more
+/// expensive to take and the histogram uses `shards` times the memory.
+pub struct ShardedHistogram {
+    config: Config,
+    lines_per_shard: usize,
+    lines: Box<[Line]>,
+}
+
+impl ShardedHistogram {
+    /// Construct a new sharded histogram from the provided parameters. See the
+    /// documentation for [`crate::Config`] to understand their meaning.
+    ///
+    /// A good choice for the number of shards is the number of threads which
+    /// concurrently record into the histogram.
+    pub fn new(grouping_power: u8, max_value_power: u8, shards: usize) -> Result<Self, Error> {
+        let config = Config::new(grouping_power, max_value_power)?;
+
+        Self::with_config(&config, shards)
+    }
+
+    /// Creates a new sharded histogram using a provided [`crate::Config`] and
+    /// number of shards.
+    ///
+    /// An error is returned if the number of shards is zero.
+    pub fn with_config(config: &Config, shards: usize) -> Result<Self, Error> {
+        if shards == 0 {
+            return Err(Error::IncompatibleParameters);
+        }
+
+        let lines_per_shard = config.total_buckets().div_ceil(LINE_COUNTERS);
+
+        let mut lines = Vec::with_capacity(shards * lines_per_shard);
+        lines.resize_with(shards * lines_per_shard, Line::new);
+
+        Ok(Self {
+            config: *config,
+            lines_per_shard,
+            lines: lines.into(),
+        })
+    }
+
+    /// Increment the bucket that contains the value by one.
+    pub fn increment(&self, value: u64) -> Result<(), Error> {
+        self.add(value, 1)
+    }
+
+    /// Increment the bucket that contains the value by some count.
+    pub fn add(&self, value: u64, count: u64) -> Result<(), Error> {
+        let index = self.config.value_to_index(value)?;
+        let shard = SHARD.with(|shard| *shard) % self.shards();
+        let line = shard * self.lines_per_shard + index / LINE_COUNTERS;
+        self.lines[line].0[index % LINE_COUNTERS].fetch_add(count, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Returns the number of shards.
+    pub fn shards(&self) -> usize {
+        self.lines.len() / self.lines_per_shard
+    }
+
+    /// Returns the bucket configuration of the histogram.
+    pub fn config(&self) -> Config {
+        self.config
+    }
+
+    // NOTE: once stabilized, `target_has_atomic_load_store` is more correct. https://github.com/rust-lang/rust/issues/94039
+    #[cfg(target_has_atomic = "64")]
+    /// Drains the bucket values from all shards into a new Histogram
+    ///
+    /// Unlike [`load`](ShardedHistogram::load), this method will reset all
+    /// bucket values to zero. This uses [`AtomicU64::swap`] and is not
+    /// available on platforms where [`AtomicU64::swap`] is not available.
+    pub fn drain(&self) -> Histogram {
+        self.merge(|counter| counter.swap(0, Ordering::Relaxed))
+    }
+
+    /// Read the bucket values from all shards into a new `Histogram`
+    pub fn load(&self) -> Histogram {
+        self.merge(|counter| counter.load(Ordering::Relaxed))
+    }
+
+    fn merge(&self, read: impl Fn(&AtomicU64) -> u64) -> Histogram {
+        let mut histogram = Histogram::with_config(&self.config);
+
+        for shard in self.lines.chunks(self.lines_per_shard) {
+            let counters = shard.iter().flat_map(|line| line.0.iter());
+
+            for (bucket, counter) in histogram.buckets.iter_mut().zip(counters) {
+                *bucket = bucket.wrapping_add(read(counter));
+            }
+        }
+
+        histogram
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::*;
+
+    #[test]
+    fn alignment() {
+        assert_eq!(std::mem::align_of::<super::Line>(), 128);
+        assert_eq!(std::mem::size_of::<super::Line>(), 128);
+    }
+
+    #[test]
+    // Tests that shards are merged when loading
+    fn load() {
+        assert!(ShardedHistogram::new(7, 64, 0).is_err());
+
+        let histogram = ShardedHistogram::new(7, 64, 4).unwrap();
+        assert_eq!(histogram.shards(), 4);
+
+        std::thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for i in 0..=100 {
+                        histogram.increment(i).unwrap();
+                    }
+                });
+            }
+        });
+
+        let loaded = histogram.load();
+        assert_eq!(loaded.statistics().count(), 808);
+        assert_eq!(
+            loaded.percentile(50.0),
+            Ok(Some(Bucket {
+                count: 8,
+                range: 50..=50,
+            }))
+        );
+    }
+
+    #[cfg(target_has_atomic = "64")]
+    #[test]
+    // Tests that drain resets all shards
+    fn drain() {
+        let histogram = ShardedHistogram::new(7, 64, 2).unwrap();
+
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| histogram.increment(1000).unwrap());
+            }
+        });
+
+        assert_eq!(histogram.drain().statistics().count(), 4);
+        assert_eq!(histogram.load().statistics().count(), 0);
+    }
+}
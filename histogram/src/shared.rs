@@ -0,0 +1,336 @@
+//! Atomic histograms whose counters are in a region of memory provided by the
+//! caller, such as a memory-mapped file under `/dev/shm`, so that another
+//! process can read them without any IPC.
+//!
+//! The region is an array of `AtomicU64` words. The first `HEADER_LEN` words
+//! are a header which describes the histogram and the bucket counters follow:
+//!
+//! | word   | contents                                                      |
+//! |--------|---------------------------------------------------------------|
+//! | `0`    | magic number, written last once the region is initialized     |
+//! | `1..3` | the versioned configuration, as in [`Histogram::to_bytes`]    |
+//! | `3`    | the number of buckets                                         |
+//! | `4..`  | the bucket counters                                           |
+//!
+//! The configuration bytes are little-endian and zero padded.
+//!
+//! [`SharedHistogram`] borrows the region, so it can be used without `unsafe`
+//! when the region is owned by the caller. [`AtomicHistogram::create_shared`]
+//! and [`AtomicHistogram::open_shared`] take a pointer and length instead, so
+//! that the region can be a mapping which the caller unmaps once the histogram
+//! has been dropped. The caller is responsible for keeping it valid until then.
+//!
+//! [`Histogram::to_bytes`]: crate::Histogram::to_bytes
+
+use crate::atomic::Buckets;
+use crate::encoding::{read_config, write_config, MAX_CONFIG_LEN};
+use crate::{AtomicHistogram, Config, Error};
+use clocksource::precise::AtomicUnixInstant;
+use core::marker::PhantomData;
+use core::ops::Deref;
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Identifies an initialized region, the bytes are `h2histo` followed by the
+/// layout version.
+const MAGIC: u64 = u64::from_be_bytes(*b"h2histo\x01");
+
+/// The number of words used for the configuration.
+const CONFIG_WORDS: usize = MAX_CONFIG_LEN.div_ceil(8);
+
+/// The number of words in the header which precedes the bucket counters.
+const HEADER_LEN: usize = 2 + CONFIG_WORDS;
+
+impl AtomicHistogram {
+    /// Returns the number of `AtomicU64` words needed for a shared region
+    /// holding a histogram with the provided [`crate::Config`], including the
+    /// header.
+    pub fn shared_len(config: &Config) -> usize {
+        HEADER_LEN + config.total_buckets()
+    }
+
+    /// Creates a new atomic histogram using a provided [`crate::Config`] with
+    /// the counters in a region of memory provided by the caller, such as a
+    /// memory-mapped file. The header is written and all of the counters are
+    /// reset to zero, after which other processes may use
+    /// [`open_shared`](AtomicHistogram::open_shared) on the same memory.
+    ///
+    /// The region must have at least
+    /// [`shared_len`](AtomicHistogram::shared_len) words, any beyond that are
+    /// left untouched. Exact values are not tracked for shared histograms and
+    /// the start time of snapshots is local to this process.
+    ///
+    /// # Safety
+    ///
+    /// `region` must be non-null and point to `len` properly aligned words
+    /// which are valid for reads and writes, and which are only accessed
+    /// atomically, for as long as the returned histogram exists.
+    pub unsafe fn create_shared(
+        config: &Config,
+        region: *const AtomicU64,
+        len: usize,
+    ) -> Result<Self, Error> {
+        if len < Self::shared_len(config) {
+            return Err(Error::IncompatibleParameters);
+        }
+
+        // SAFETY: upheld by the caller
+        let region = unsafe { core::slice::from_raw_parts(region, Self::shared_len(config)) };
+        let (header, buckets) = region.split_at(HEADER_LEN);
+
+        // readers must not see the header until it is complete
+        header[0].store(0, Ordering::Release);
+
+        let mut bytes = Vec::with_capacity(CONFIG_WORDS * 8);
+        write_config(&mut bytes, config);
+        bytes.resize(CONFIG_WORDS * 8, 0);
+
+        for (word, chunk) in header[1..=CONFIG_WORDS].iter().zip(bytes.chunks_exact(8)) {
+            word.store(
+                u64::from_le_bytes(chunk.try_into().unwrap()),
+                Ordering::Relaxed,
+            );
+        }
+
+        header[HEADER_LEN - 1].store(config.total_buckets() as u64, Ordering::Relaxed);
+
+        for bucket in buckets {
+            bucket.store(0, Ordering::Relaxed);
+        }
+
+        header[0].store(MAGIC, Ordering::Release);
+
+        // SAFETY: upheld by the caller
+        Ok(unsafe { Self::shared(*config, buckets) })
+    }
+
+    /// Opens an atomic histogram in a region of memory which was initialized
+    /// by [`create_shared`](AtomicHistogram::create_shared), which may have
+    /// happened in another process. The counters are left as they are.
+    ///
+    /// An error is returned if the header is not valid or if the region is
+    /// too short for the number of buckets the configuration requires.
+    ///
+    /// # Safety
+    ///
+    /// `region` must be non-null and point to `len` properly aligned words
+    /// which are valid for reads and writes, and which are only accessed
+    /// atomically, for as long as the returned histogram exists.
+    pub unsafe fn open_shared(region: *const AtomicU64, len: usize) -> Result<Self, Error> {
+        if len < HEADER_LEN {
+            return Err(Error::InvalidEncoding);
+        }
+
+        // SAFETY: upheld by the caller
+        let region = unsafe { core::slice::from_raw_parts(region, len) };
+        let (header, buckets) = region.split_at(HEADER_LEN);
+
+        if header[0].load(Ordering::Acquire) != MAGIC {
+            return Err(Error::InvalidEncoding);
+        }
+
+        let mut bytes = Vec::with_capacity(CONFIG_WORDS * 8);
+        for word in &header[1..=CONFIG_WORDS] {
+            bytes.extend_from_slice(&word.load(Ordering::Relaxed).to_le_bytes());
+        }
+
+        let mut remaining = &bytes[..];
+        let config = read_config(&mut remaining)?;
+
+        // anything after the configuration must be padding
+        if remaining.iter().any(|b| *b != 0) {
+            return Err(Error::InvalidEncoding);
+        }
+
+        let total_buckets = header[HEADER_LEN - 1].load(Ordering::Relaxed);
+
+        if total_buckets != config.total_buckets() as u64 || buckets.len() < config.total_buckets()
+        {
+            return Err(Error::InvalidEncoding);
+        }
+
+        // SAFETY: upheld by the caller
+        Ok(unsafe { Self::shared(config, &buckets[..config.total_buckets()]) })
+    }
+
+    /// Returns true if the counters are in a region of memory provided by the
+    /// caller.
+    pub fn is_shared(&self) -> bool {
+        matches!(self.buckets, Buckets::Shared(..))
+    }
+
+    /// # Safety
+    ///
+    /// The buckets must stay valid for as long as the histogram exists, even
+    /// though the borrow may end sooner.
+    unsafe fn shared(config: Config, buckets: &[AtomicU64]) -> Self {
+        Self {
+            config,
+            buckets: Buckets::Shared(NonNull::from(buckets).cast(), buckets.len()),
+            start: AtomicUnixInstant::now(),
+            exact: None,
+        }
+    }
+}
+
+/// An atomic histogram with the counters in a region of memory borrowed from
+/// the caller. This is a safe alternative to
+/// [`AtomicHistogram::create_shared`] and [`AtomicHistogram::open_shared`] for
+/// regions which outlive the histogram, and it dereferences to an
+/// [`AtomicHistogram`].
+pub struct SharedHistogram<'a> {
+    histogram: AtomicHistogram,
+    _region: PhantomData<&'a [AtomicU64]>,
+}
+
+impl<'a> SharedHistogram<'a> {
+    /// Creates a new atomic histogram using a provided [`crate::Config`] with
+    /// the counters in the provided region. The header is written and all of
+    /// the counters are reset to zero, as in
+    /// [`create_shared`](AtomicHistogram::create_shared).
+    pub fn create(config: &Config, region: &'a [AtomicU64]) -> Result<Self, Error> {
+        // SAFETY: the region is borrowed for as long as the histogram exists
+        // and it can only be accessed atomically while it is borrowed
+        let histogram =
+            unsafe { AtomicHistogram::create_shared(config, region.as_ptr(), region.len())? };
+
+        Ok(Self {
+            histogram,
+            _region: PhantomData,
+        })
+    }
+
+    /// Opens an atomic histogram in the provided region, which must have been
+    /// initialized by [`create`](SharedHistogram::create) or
+    /// [`create_shared`](AtomicHistogram::create_shared), as in
+    /// [`open_shared`](AtomicHistogram::open_shared).
+    pub fn open(region: &'a [AtomicU64]) -> Result<Self, Error> {
+        // SAFETY: as above
+        let histogram = unsafe { AtomicHistogram::open_shared(region.as_ptr(), region.len())? };
+
+        Ok(Self {
+            histogram,
+            _region: PhantomData,
+        })
+    }
+}
+
+impl Deref for SharedHistogram<'_> {
+    type Target = AtomicHistogram;
+
+    fn deref(&self) -> &AtomicHistogram {
+        &self.histogram
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(len: usize) -> Vec<AtomicU64> {
+        let mut region = Vec::with_capacity(len);
+        region.resize_with(len, || AtomicU64::new(u64::MAX));
+        region
+    }
+
+    fn create(config: &Config, region: &[AtomicU64]) -> Result<AtomicHistogram, Error> {
+        unsafe { AtomicHistogram::create_shared(config, region.as_ptr(), region.len()) }
+    }
+
+    fn open(region: &[AtomicU64]) -> Result<AtomicHistogram, Error> {
+        unsafe { AtomicHistogram::open_shared(region.as_ptr(), region.len()) }
+    }
+
+    #[test]
+    // Tests the borrowing constructors
+    fn borrowed() {
+        let config = Config::new(7, 64).unwrap();
+        let region = region(AtomicHistogram::shared_len(&config));
+
+        let writer = SharedHistogram::create(&config, &region).unwrap();
+        assert!(writer.is_shared());
+
+        let reader = SharedHistogram::open(&region).unwrap();
+        writer.increment(42).unwrap();
+        assert_eq!(reader.load(), writer.load());
+        assert_eq!(reader.load().as_slice().iter().sum::<u64>(), 1);
+
+        // the unsafe constructors see the same region
+        assert_eq!(open(&region).unwrap().load(), writer.load());
+
+        assert!(matches!(
+            SharedHistogram::create(&config, &region[1..]),
+            Err(Error::IncompatibleParameters)
+        ));
+        assert!(matches!(
+            SharedHistogram::open(&region[..2]),
+            Err(Error::InvalidEncoding)
+        ));
+    }
+
+    #[test]
+    // Tests that a reader sees the counters of a writer
+    fn shared() {
+        for config in [
+            Config::new(7, 64).unwrap(),
+            Config::new(0, 1).unwrap(),
+            Config::logarithmic(0.01, 32).unwrap(),
+        ] {
+            let region = region(AtomicHistogram::shared_len(&config) + 3);
+
+            let writer = create(&config, &region).unwrap();
+            assert!(writer.is_shared());
+            assert_eq!(writer.load().as_slice().iter().sum::<u64>(), 0);
+
+            let reader = open(&region).unwrap();
+            assert_eq!(reader.load().config(), config);
+
+            writer.add(1, 5).unwrap();
+            assert_eq!(reader.load(), writer.load());
+            assert_eq!(reader.load().as_slice().iter().sum::<u64>(), 5);
+
+            // trailing words are not used
+            assert_eq!(region[region.len() - 1].load(Ordering::Relaxed), u64::MAX);
+
+            // the region outlives the histograms without being leaked
+            drop(writer);
+            drop(reader);
+        }
+
+        assert!(!AtomicHistogram::new(7, 64).unwrap().is_shared());
+    }
+
+    #[test]
+    // Tests that invalid regions are rejected
+    fn validation() {
+        let config = Config::new(7, 64).unwrap();
+        let len = AtomicHistogram::shared_len(&config);
+        assert_eq!(len, 4 + 7424);
+
+        assert!(matches!(
+            create(&config, &region(len - 1)),
+            Err(Error::IncompatibleParameters)
+        ));
+
+        // not initialized
+        assert!(matches!(open(&region(len)), Err(Error::InvalidEncoding)));
+        assert!(matches!(open(&region(2)), Err(Error::InvalidEncoding)));
+
+        // too short for the buckets
+        let region = region(len);
+        create(&config, &region).unwrap();
+        assert!(matches!(
+            open(&region[..len - 1]),
+            Err(Error::InvalidEncoding)
+        ));
+
+        // bucket count does not match the configuration
+        region[3].store(7423, Ordering::Relaxed);
+        assert!(matches!(open(&region), Err(Error::InvalidEncoding)));
+        region[3].store(7424, Ordering::Relaxed);
+
+        // unsupported configuration version
+        region[1].store(3, Ordering::Relaxed);
+        assert!(matches!(open(&region), Err(Error::UnsupportedVersion)));
+    }
+}
@@ -0,0 +1,216 @@
+use crate::percentile::ranks;
+use crate::{Config, Error, Histogram, Interpolation};
+use core::ops::RangeInclusive;
+
+/// A histogram which records signed values, such as clock skew.
+///
+/// Values are split by sign and their magnitudes are recorded using the
+/// bucketing scheme of the provided [`crate::Config`], so the relative error
+/// is the same on both sides of zero. The `max_value_power` bounds the
+/// magnitude of values in either direction. Percentiles are reported in the
+/// signed domain.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignedHistogram {
+    negative: Histogram,
+    positive: Histogram,
+}
+
+impl SignedHistogram {
+    /// Construct a new histogram from the provided parameters. See the
+    /// documentation for [`crate::Config`] to understand their meaning.
+    pub fn new(grouping_power: u8, max_value_power: u8) -> Result<Self, Error> {
+        let config = Config::new(grouping_power, max_value_power)?;
+
+        Ok(Self::with_config(&config))
+    }
+
+    /// Creates a new histogram using a provided [`crate::Config`].
+    pub fn with_config(config: &Config) -> Self {
+        Self {
+            negative: Histogram::with_config(config),
+            positive: Histogram::with_config(config),
+        }
+    }
+
+    /// Increment the counter for the bucket corresponding to the provided value
+    /// by one.
+    pub fn increment(&mut self, value: i64) -> Result<(), Error> {
+        self.add(value, 1)
+    }
+
+    /// Add some count to the counter for the bucket corresponding to the
+    /// provided value.
+    pub fn add(&mut self, value: i64, count: u64) -> Result<(), Error> {
+        if value < 0 {
+            self.negative.add(value.unsigned_abs(), count)
+        } else {
+            self.positive.add(value as u64, count)
+        }
+    }
+
+    /// Returns a histogram of the magnitudes of the negative values.
+    pub fn negative(&self) -> &Histogram {
+        &self.negative
+    }
+
+    /// Returns a histogram of the non-negative values.
+    pub fn positive(&self) -> &Histogram {
+        &self.positive
+    }
+
+    /// Returns the bucket configuration of the histogram.
+    pub fn config(&self) -> Config {
+        self.positive.config()
+    }
+
+    /// Return a collection of percentiles from this histogram as the range of
+    /// signed values in the bucket which contains each percentile.
+    ///
+    /// Each percentile should be in the inclusive range `0.0..=100.0`. The
+    /// results will be sorted by the percentile.
+    #[allow(clippy::type_complexity)]
+    pub fn percentiles(
+        &self,
+        percentiles: &[f64],
+    ) -> Result<Option<Vec<(f64, RangeInclusive<i64>)>>, Error> {
+        Ok(ranks(percentiles, self.buckets())?.map(|ranks| {
+            ranks
+                .iter()
+                .map(|rank| (rank.percentile, self.range(rank.index)))
+                .collect()
+        }))
+    }
+
+    /// Return a single percentile from this histogram as the range of signed
+    /// values in the bucket which contains it.
+    ///
+    /// The percentile should be in the inclusive range `0.0..=100.0`.
+    pub fn percentile(&self, percentile: f64) -> Result<Option<RangeInclusive<i64>>, Error> {
+        self.percentiles(&[percentile])
+            .map(|v| v.map(|x| x[0].1.clone()))
+    }
+
+    /// Return a collection of estimated signed values for the percentiles from
+    /// this histogram, using the provided [`crate::Interpolation`] strategy.
+    ///
+    /// Each percentile should be in the inclusive range `0.0..=100.0`. The
+    /// results will be sorted by the percentile.
+    pub fn percentile_values(
+        &self,
+        percentiles: &[f64],
+        interpolation: Interpolation,
+    ) -> Result<Option<Vec<(f64, f64)>>, Error> {
+        Ok(ranks(percentiles, self.buckets())?.map(|ranks| {
+            ranks
+                .iter()
+                .map(|rank| {
+                    let range = self.range(rank.index);
+                    let value =
+                        interpolation.estimate(*range.start() as f64, *range.end() as f64, rank);
+                    (rank.percentile, value)
+                })
+                .collect()
+        }))
+    }
+
+    /// Return an estimated signed value for a single percentile from this
+    /// histogram, using the provided [`crate::Interpolation`] strategy.
+    ///
+    /// The percentile should be in the inclusive range `0.0..=100.0`.
+    pub fn percentile_value(
+        &self,
+        percentile: f64,
+        interpolation: Interpolation,
+    ) -> Result<Option<f64>, Error> {
+        self.percentile_values(&[percentile], interpolation)
+            .map(|v| v.map(|x| x[0].1))
+    }
+
+    /// Returns all buckets in increasing order of value. Negative buckets are
+    /// visited from the largest magnitude down, so their indices are mirrored.
+    fn buckets(&self) -> impl Iterator<Item = (usize, u64)> + Clone + '_ {
+        let total = self.config().total_buckets();
+
+        let negative = self.negative.as_slice().iter().rev().copied().enumerate();
+        let positive = self
+            .positive
+            .as_slice()
+            .iter()
+            .copied()
+            .enumerate()
+            .map(move |(index, count)| (total + index, count));
+
+        negative.chain(positive)
+    }
+
+    /// Converts an index from `buckets()` into a range of signed values. Bounds
+    /// which cannot be represented are clamped to the range of `i64`.
+    fn range(&self, index: usize) -> RangeInclusive<i64> {
+        let config = self.config();
+        let total = config.total_buckets();
+
+        if index < total {
+            let range = config.index_to_range(total - 1 - index);
+            let start = (-(*range.end() as i128)).max(i64::MIN as i128) as i64;
+            let end = (-(*range.start() as i128)).max(i64::MIN as i128) as i64;
+            start..=end
+        } else {
+            let range = config.index_to_range(index - total);
+            let start = (*range.start()).min(i64::MAX as u64) as i64;
+            let end = (*range.end()).min(i64::MAX as u64) as i64;
+            start..=end
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    // Tests percentiles across both signs
+    fn percentiles() {
+        let mut histogram = SignedHistogram::new(2, 64).unwrap();
+        assert_eq!(histogram.percentile(50.0), Ok(None));
+
+        for v in -50..50 {
+            histogram.increment(v).unwrap();
+        }
+
+        // -50 has a magnitude in the bucket 48..=55
+        assert_eq!(histogram.percentile(0.0), Ok(Some(-55..=-48)));
+        assert_eq!(histogram.percentile(50.0), Ok(Some(-1..=-1)));
+        assert_eq!(histogram.percentile(51.0), Ok(Some(0..=0)));
+        assert_eq!(histogram.percentile(100.0), Ok(Some(48..=55)));
+
+        assert_eq!(
+            histogram.percentile_values(&[0.0, 100.0], Interpolation::Upper),
+            Ok(Some(vec![(0.0, -48.0), (100.0, 55.0)]))
+        );
+        assert_eq!(
+            histogram.percentile_value(1.0, Interpolation::Linear),
+            Ok(Some(-55.0 + 7.0 / 3.0))
+        );
+    }
+
+    #[test]
+    // Tests the extremes of the signed range
+    fn extremes() {
+        let mut histogram = SignedHistogram::new(7, 64).unwrap();
+        histogram.increment(i64::MIN).unwrap();
+        histogram.increment(i64::MAX).unwrap();
+
+        assert_eq!(histogram.negative().statistics().count(), 1);
+        assert_eq!(
+            histogram.percentile(0.0).unwrap().unwrap().start(),
+            &i64::MIN
+        );
+        assert_eq!(
+            histogram.percentile(100.0).unwrap().unwrap().end(),
+            &i64::MAX
+        );
+
+        let mut histogram = SignedHistogram::new(7, 32).unwrap();
+        assert_eq!(histogram.increment(-(1 << 40)), Err(Error::OutOfRange));
+    }
+}
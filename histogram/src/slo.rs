@@ -0,0 +1,206 @@
+use crate::{Bucket, Error};
+
+/// A service level objective which requires that some target fraction of the
+/// observations are at or below a threshold value.
+///
+/// An objective such as "p99 is at most 10ms" is the same as "99% of requests
+/// take at most 10ms", so both forms can be constructed and are evaluated in
+/// the same way. The fraction of observations allowed to exceed the threshold
+/// is the error budget.
+///
+/// Objectives are evaluated against any collection of buckets, such as a
+/// [`crate::Histogram`], a [`crate::SparseHistogram`], or the recent history
+/// of a [`crate::WindowedHistogram`] from
+/// [`WindowedHistogram::last`](crate::WindowedHistogram::last).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Objective {
+    threshold: u64,
+    target: f64,
+}
+
+impl Objective {
+    /// Creates an objective which requires that the `target` fraction of the
+    /// observations are at or below the threshold. For example, a target of
+    /// `0.999` with a threshold of `50` means 99.9% of values are at most 50.
+    ///
+    /// The target should be in the inclusive range `0.0..=1.0`.
+    pub fn new(threshold: u64, target: f64) -> Result<Self, Error> {
+        if target.is_nan() || !(0.0..=1.0).contains(&target) {
+            return Err(Error::InvalidPercentile);
+        }
+
+        Ok(Self { threshold, target })
+    }
+
+    /// Creates an objective which requires that the value at the percentile is
+    /// at or below the threshold. For example, a percentile of `99.0` with a
+    /// threshold of `10` means p99 is at most 10.
+    ///
+    /// The percentile should be in the inclusive range `0.0..=100.0`.
+    pub fn percentile(percentile: f64, threshold: u64) -> Result<Self, Error> {
+        if percentile.is_nan() || !(0.0..=100.0).contains(&percentile) {
+            return Err(Error::InvalidPercentile);
+        }
+
+        Self::new(threshold, percentile / 100.0)
+    }
+
+    /// Returns the threshold value.
+    pub fn threshold(&self) -> u64 {
+        self.threshold
+    }
+
+    /// Returns the fraction of observations which should be at or below the
+    /// threshold.
+    pub fn target(&self) -> f64 {
+        self.target
+    }
+
+    /// Evaluates the objective against the buckets of a histogram.
+    ///
+    /// A bucket's observations are only counted as meeting the objective if
+    /// the whole bucket is at or below the threshold. When the threshold is
+    /// not the upper bound of a bucket, the bucket which contains it is
+    /// counted against the objective, so the result is conservative.
+    pub fn evaluate(&self, buckets: impl IntoIterator<Item = Bucket>) -> Evaluation {
+        let mut good = 0;
+        let mut bad = 0;
+
+        for bucket in buckets {
+            if bucket.end() <= self.threshold {
+                good += bucket.count() as u128;
+            } else {
+                bad += bucket.count() as u128;
+            }
+        }
+
+        Evaluation {
+            objective: *self,
+            good,
+            bad,
+        }
+    }
+}
+
+/// The result of evaluating an [`Objective`] against a histogram.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Evaluation {
+    objective: Objective,
+    good: u128,
+    bad: u128,
+}
+
+impl Evaluation {
+    /// Returns the objective which was evaluated.
+    pub fn objective(&self) -> Objective {
+        self.objective
+    }
+
+    /// Returns the number of observations at or below the threshold.
+    pub fn good(&self) -> u128 {
+        self.good
+    }
+
+    /// Returns the number of observations above the threshold.
+    pub fn bad(&self) -> u128 {
+        self.bad
+    }
+
+    /// Returns the total number of observations.
+    pub fn total(&self) -> u128 {
+        self.good + self.bad
+    }
+
+    /// Returns the fraction of observations at or below the threshold, or
+    /// `None` if there are no observations.
+    pub fn fraction(&self) -> Option<f64> {
+        (self.total() != 0).then(|| self.good as f64 / self.total() as f64)
+    }
+
+    /// Returns true if the objective was met. An objective is always met when
+    /// there are no observations.
+    pub fn passed(&self) -> bool {
+        self.fraction()
+            .is_none_or(|fraction| fraction >= self.objective.target)
+    }
+
+    /// Returns the fraction of the error budget which was consumed, where
+    /// `1.0` means the budget was used exactly. Returns `None` if there are no
+    /// observations.
+    ///
+    /// If the target is `1.0` there is no budget, so any observation above the
+    /// threshold consumes an infinite fraction of it.
+    pub fn budget_consumed(&self) -> Option<f64> {
+        let budget = 1.0 - self.objective.target;
+
+        self.fraction().map(|fraction| {
+            let spent = 1.0 - fraction;
+            if spent == 0.0 {
+                0.0
+            } else {
+                spent / budget
+            }
+        })
+    }
+
+    /// Returns the fraction of the error budget which remains, which is
+    /// negative once the budget has been exceeded. Returns `None` if there are
+    /// no observations.
+    pub fn budget_remaining(&self) -> Option<f64> {
+        self.budget_consumed().map(|consumed| 1.0 - consumed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Histogram, SparseHistogram};
+
+    #[test]
+    // Tests that objectives are validated
+    fn objective() {
+        assert_eq!(Objective::new(10, 1.5), Err(Error::InvalidPercentile));
+        assert_eq!(Objective::new(10, f64::NAN), Err(Error::InvalidPercentile));
+        assert_eq!(
+            Objective::percentile(101.0, 10),
+            Err(Error::InvalidPercentile)
+        );
+        assert_eq!(
+            Objective::percentile(99.0, 10),
+            Ok(Objective::new(10, 0.99).unwrap())
+        );
+    }
+
+    #[test]
+    // Tests evaluation and the error budget
+    fn evaluate() {
+        let mut histogram = Histogram::new(7, 32).unwrap();
+
+        let objective = Objective::percentile(90.0, 100).unwrap();
+        let evaluation = objective.evaluate(&histogram);
+        assert!(evaluation.passed());
+        assert_eq!(evaluation.budget_consumed(), None);
+
+        for v in 1..=100 {
+            histogram.increment(v).unwrap();
+        }
+        histogram.add(1000, 5).unwrap();
+
+        let evaluation = objective.evaluate(&histogram);
+        assert_eq!(evaluation.good(), 100);
+        assert_eq!(evaluation.bad(), 5);
+        assert!(evaluation.passed());
+        assert!((evaluation.budget_consumed().unwrap() - 5.0 / 10.5).abs() < 1e-9);
+
+        let strict = Objective::new(100, 0.99).unwrap();
+        let evaluation = strict.evaluate(&SparseHistogram::from(&histogram));
+        assert!(!evaluation.passed());
+        assert!(evaluation.budget_remaining().unwrap() < 0.0);
+
+        let perfect = Objective::new(100, 1.0).unwrap();
+        assert_eq!(
+            perfect.evaluate(&histogram).budget_consumed(),
+            Some(f64::INFINITY)
+        );
+    }
+}
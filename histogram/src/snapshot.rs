@@ -0,0 +1,184 @@
+use crate::{Error, Histogram};
+use clocksource::precise::{Duration, UnixInstant};
+
+/// A histogram along with the range of time over which it was recorded.
+///
+/// Snapshots are typically taken from an [`crate::AtomicHistogram`] using
+/// [`load_snapshot`](crate::AtomicHistogram::load_snapshot) or
+/// [`drain_snapshot`](crate::AtomicHistogram::drain_snapshot). Knowing the
+/// time range allows for computing rates and for correctly combining
+/// snapshots which were taken at different times.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    start: UnixInstant,
+    end: UnixInstant,
+    histogram: Histogram,
+}
+
+impl Snapshot {
+    /// Create a new snapshot which covers the time range from `start` to `end`.
+    ///
+    /// An error is returned if `end` is before `start`.
+    pub fn new(start: UnixInstant, end: UnixInstant, histogram: Histogram) -> Result<Self, Error> {
+        if end < start {
+            return Err(Error::IncompatibleTimeRange);
+        }
+
+        Ok(Self {
+            start,
+            end,
+            histogram,
+        })
+    }
+
+    /// Returns the instant at which recording for this snapshot began.
+    pub fn start(&self) -> UnixInstant {
+        self.start
+    }
+
+    /// Returns the instant at which this snapshot was taken.
+    pub fn end(&self) -> UnixInstant {
+        self.end
+    }
+
+    /// Returns the duration of time covered by this snapshot.
+    pub fn duration(&self) -> Duration {
+        self.end - self.start
+    }
+
+    /// Returns a reference to the histogram for this snapshot.
+    pub fn histogram(&self) -> &Histogram {
+        &self.histogram
+    }
+
+    /// Consumes the snapshot and returns the histogram.
+    pub fn into_histogram(self) -> Histogram {
+        self.histogram
+    }
+
+    /// Adds the other snapshot to this snapshot and returns the result as a
+    /// new snapshot. The two snapshots must cover adjacent time ranges, with
+    /// one ending exactly when the other begins.
+    ///
+    /// An error is returned if the two snapshots have incompatible parameters,
+    /// if their time ranges are not adjacent, or if there is an overflow.
+    pub fn checked_add(&self, other: &Snapshot) -> Result<Snapshot, Error> {
+        let (start, end) = if self.end == other.start {
+            (self.start, other.end)
+        } else if other.end == self.start {
+            (other.start, self.end)
+        } else {
+            return Err(Error::IncompatibleTimeRange);
+        };
+
+        Ok(Snapshot {
+            start,
+            end,
+            histogram: self.histogram.checked_add(&other.histogram)?,
+        })
+    }
+
+    /// Subtracts the other snapshot from this snapshot and returns the result
+    /// as a new snapshot which covers the remaining time range.
+    ///
+    /// The other snapshot must cover a prefix or a suffix of this snapshot's
+    /// time range. For example, subtracting an earlier snapshot of a
+    /// cumulative histogram from a later one (both sharing the same start)
+    /// produces a snapshot of the interval between them.
+    ///
+    /// An error is returned if the two snapshots have incompatible parameters,
+    /// if the time ranges do not line up, or if there is an overflow.
+    pub fn checked_sub(&self, other: &Snapshot) -> Result<Snapshot, Error> {
+        if other.start < self.start || other.end > self.end {
+            return Err(Error::IncompatibleTimeRange);
+        }
+
+        let (start, end) = if self.start == other.start {
+            (other.end, self.end)
+        } else if self.end == other.end {
+            (self.start, other.start)
+        } else {
+            return Err(Error::IncompatibleTimeRange);
+        };
+
+        Ok(Snapshot {
+            start,
+            end,
+            histogram: self.histogram.checked_sub(&other.histogram)?,
+        })
+    }
+}
+
+impl From<Snapshot> for Histogram {
+    fn from(snapshot: Snapshot) -> Self {
+        snapshot.histogram
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instant(secs: u32) -> UnixInstant {
+        UnixInstant::EPOCH + Duration::from_secs(secs)
+    }
+
+    fn build(start: u32, end: u32, value: u64) -> Snapshot {
+        let mut histogram = Histogram::new(7, 64).unwrap();
+        histogram.increment(value).unwrap();
+        Snapshot::new(instant(start), instant(end), histogram).unwrap()
+    }
+
+    #[test]
+    // Test that snapshots must have ordered instants
+    fn new() {
+        let histogram = Histogram::new(7, 64).unwrap();
+        assert_eq!(
+            Snapshot::new(instant(2), instant(1), histogram),
+            Err(Error::IncompatibleTimeRange)
+        );
+
+        let snapshot = build(1, 11, 5);
+        assert_eq!(snapshot.duration(), Duration::from_secs(10));
+    }
+
+    #[test]
+    // Test adding adjacent snapshots
+    fn checked_add() {
+        let a = build(0, 10, 1);
+        let b = build(10, 20, 2);
+
+        let r = a.checked_add(&b).unwrap();
+        assert_eq!(r.start(), instant(0));
+        assert_eq!(r.end(), instant(20));
+        assert_eq!(r.histogram().as_slice().iter().sum::<u64>(), 2);
+        assert_eq!(b.checked_add(&a), Ok(r));
+
+        let c = build(15, 30, 3);
+        assert_eq!(a.checked_add(&c), Err(Error::IncompatibleTimeRange));
+    }
+
+    #[test]
+    // Test subtracting cumulative and partial snapshots
+    fn checked_sub() {
+        let early = build(0, 10, 1);
+        let late = build(0, 20, 1).checked_add(&build(20, 30, 2)).unwrap();
+
+        let r = late.checked_sub(&early).unwrap();
+        assert_eq!(r.start(), instant(10));
+        assert_eq!(r.end(), instant(30));
+        assert_eq!(r.histogram().as_slice().iter().sum::<u64>(), 1);
+
+        let suffix = build(20, 30, 2);
+        let r = late.checked_sub(&suffix).unwrap();
+        assert_eq!(r.start(), instant(0));
+        assert_eq!(r.end(), instant(20));
+
+        let middle = build(5, 15, 1);
+        assert_eq!(late.checked_sub(&middle), Err(Error::IncompatibleTimeRange));
+        assert_eq!(early.checked_sub(&late), Err(Error::IncompatibleTimeRange));
+
+        let overflow = build(0, 10, 2);
+        assert_eq!(early.checked_sub(&overflow), Err(Error::Overflow));
+    }
+}
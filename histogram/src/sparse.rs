@@ -1,4 +1,11 @@
-use crate::{Bucket, Config, Error, Histogram};
+use crate::cdf::{count_below, count_in_range, Cumulative};
+use crate::distribution;
+use crate::percentile::{percentiles_into, ranks};
+use crate::rebucket::rebucket;
+use crate::{Bucket, Config, Error, Histogram, Interpolation, Overflow, Rebucketing, Statistics};
+use core::ops::RangeInclusive;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
 
 /// This histogram is a sparse, columnar representation of the regular
 /// Histogram. It is significantly smaller than a regular Histogram
@@ -37,6 +44,22 @@ impl SparseHistogram {
         }
     }
 
+    /// Replaces the contents of this histogram with the non-zero buckets of
+    /// the provided [`crate::Histogram`], taking its configuration.
+    ///
+    /// Unlike converting with `From`, the existing storage is reused, so
+    /// nothing is allocated once it has enough capacity. As with `From`, any
+    /// [`crate::Exact`] values of the histogram are not kept.
+    pub fn copy_from(&mut self, histogram: &Histogram) {
+        self.config = histogram.config();
+        self.index.clear();
+        self.count.clear();
+
+        for (idx, n) in histogram.as_slice().iter().enumerate() {
+            self.add_bucket(idx, *n);
+        }
+    }
+
     /// Helper function to store a bucket in the histogram.
     fn add_bucket(&mut self, idx: usize, n: u64) {
         if n != 0 {
@@ -45,13 +68,16 @@ impl SparseHistogram {
         }
     }
 
-    /// Adds the other histogram to this histogram and returns the result as a
-    /// new histogram.
-    ///
-    /// An error is returned if the two histograms have incompatible parameters.
-    /// Buckets which have values in both histograms are allowed to wrap.
+    /// Merges the buckets of this histogram with the other histogram, applying
+    /// the operation to the counts of each bucket. Buckets which are only
+    /// present in one histogram are treated as zero in the other, and buckets
+    /// which are zero in the result are dropped.
     #[allow(clippy::comparison_chain)]
-    pub fn wrapping_add(&self, h: &SparseHistogram) -> Result<SparseHistogram, Error> {
+    fn merge(
+        &self,
+        h: &SparseHistogram,
+        op: impl Fn(u64, u64) -> Result<u64, Error>,
+    ) -> Result<SparseHistogram, Error> {
         if self.config != h.config {
             return Err(Error::IncompatibleParameters);
         }
@@ -65,27 +91,147 @@ impl SparseHistogram {
             let (k2, v2) = (h.index[j], h.count[j]);
 
             if k1 == k2 {
-                histogram.add_bucket(k1, v1 + v2);
+                histogram.add_bucket(k1, op(v1, v2)?);
                 (i, j) = (i + 1, j + 1);
             } else if k1 < k2 {
-                histogram.add_bucket(k1, v1);
+                histogram.add_bucket(k1, op(v1, 0)?);
                 i += 1;
             } else {
-                histogram.add_bucket(k2, v2);
+                histogram.add_bucket(k2, op(0, v2)?);
                 j += 1;
             }
         }
 
         // Fill remaining values, if any, from the left histogram
-        if i < self.index.len() {
-            histogram.index.extend(&self.index[i..self.index.len()]);
-            histogram.count.extend(&self.count[i..self.count.len()]);
+        for (k, v) in self.index[i..].iter().zip(self.count[i..].iter()) {
+            histogram.add_bucket(*k, op(*v, 0)?);
         }
 
         // Fill remaining values, if any, from the right histogram
-        if j < h.index.len() {
-            histogram.index.extend(&h.index[j..h.index.len()]);
-            histogram.count.extend(&h.count[j..h.count.len()]);
+        for (k, v) in h.index[j..].iter().zip(h.count[j..].iter()) {
+            histogram.add_bucket(*k, op(0, *v)?);
+        }
+
+        Ok(histogram)
+    }
+
+    /// Adds the other histogram to this histogram and returns the result as a
+    /// new histogram.
+    ///
+    /// An error is returned if the two histograms have incompatible parameters
+    /// or if there is an overflow.
+    pub fn checked_add(&self, h: &SparseHistogram) -> Result<SparseHistogram, Error> {
+        self.merge(h, |a, b| a.checked_add(b).ok_or(Error::Overflow))
+    }
+
+    /// Adds the other histogram to this histogram and returns the result as a
+    /// new histogram.
+    ///
+    /// An error is returned if the two histograms have incompatible parameters.
+    /// Buckets which have values in both histograms are allowed to wrap.
+    pub fn wrapping_add(&self, h: &SparseHistogram) -> Result<SparseHistogram, Error> {
+        self.merge(h, |a, b| Ok(a.wrapping_add(b)))
+    }
+
+    /// Adds the other histogram to this histogram and returns the result as a
+    /// new histogram.
+    ///
+    /// An error is returned if the two histograms have incompatible parameters.
+    /// Buckets which would overflow are clamped to `u64::MAX`.
+    pub fn saturating_add(&self, h: &SparseHistogram) -> Result<SparseHistogram, Error> {
+        self.merge(h, |a, b| Ok(a.saturating_add(b)))
+    }
+
+    /// Subtracts the other histogram from this histogram and returns the result
+    /// as a new histogram.
+    ///
+    /// An error is returned if the two histograms have incompatible parameters.
+    /// Buckets which would underflow are allowed to wrap.
+    pub fn wrapping_sub(&self, h: &SparseHistogram) -> Result<SparseHistogram, Error> {
+        self.merge(h, |a, b| Ok(a.wrapping_sub(b)))
+    }
+
+    /// Subtracts the other histogram from this histogram and returns the result
+    /// as a new histogram.
+    ///
+    /// An error is returned if the two histograms have incompatible parameters.
+    /// Buckets which would underflow are clamped to zero and dropped.
+    pub fn saturating_sub(&self, h: &SparseHistogram) -> Result<SparseHistogram, Error> {
+        self.merge(h, |a, b| Ok(a.saturating_sub(b)))
+    }
+
+    /// Merges many histograms at once with a k-way merge of their buckets and
+    /// returns the sum as a new histogram with the provided config.
+    ///
+    /// An error is returned if any histogram has a different config or if
+    /// there is an overflow.
+    pub fn checked_sum(
+        config: &Config,
+        histograms: &[&SparseHistogram],
+    ) -> Result<SparseHistogram, Error> {
+        Self::sum(config, histograms, |a, b| {
+            a.checked_add(b).ok_or(Error::Overflow)
+        })
+    }
+
+    /// Merges many histograms at once with a k-way merge of their buckets and
+    /// returns the sum as a new histogram with the provided config.
+    ///
+    /// An error is returned if any histogram has a different config. Buckets
+    /// are allowed to wrap.
+    pub fn wrapping_sum(
+        config: &Config,
+        histograms: &[&SparseHistogram],
+    ) -> Result<SparseHistogram, Error> {
+        Self::sum(config, histograms, |a, b| Ok(a.wrapping_add(b)))
+    }
+
+    fn sum(
+        config: &Config,
+        histograms: &[&SparseHistogram],
+        op: impl Fn(u64, u64) -> Result<u64, Error>,
+    ) -> Result<SparseHistogram, Error> {
+        if histograms.iter().any(|h| h.config != *config) {
+            return Err(Error::IncompatibleParameters);
+        }
+
+        // A min-heap holding the next bucket index from each histogram, along
+        // with which histogram it came from.
+        let mut heap = BinaryHeap::with_capacity(histograms.len());
+        let mut positions = vec![0; histograms.len()];
+
+        for (source, h) in histograms.iter().enumerate() {
+            if let Some(index) = h.index.first() {
+                heap.push(Reverse((*index, source)));
+            }
+        }
+
+        let mut histogram = SparseHistogram::with_config(config);
+        let mut aggregating: Option<(usize, u64)> = None;
+
+        while let Some(Reverse((index, source))) = heap.pop() {
+            let h = histograms[source];
+            let count = h.count[positions[source]];
+
+            positions[source] += 1;
+            if let Some(next) = h.index.get(positions[source]) {
+                heap.push(Reverse((*next, source)));
+            }
+
+            // Buckets arrive in order of index, so once the index changes the
+            // aggregated bucket can be sealed
+            aggregating = match aggregating {
+                Some((i, n)) if i == index => Some((i, op(n, count)?)),
+                Some((i, n)) => {
+                    histogram.add_bucket(i, n);
+                    Some((index, count))
+                }
+                None => Some((index, count)),
+            };
+        }
+
+        if let Some((i, n)) = aggregating {
+            histogram.add_bucket(i, n);
         }
 
         Ok(histogram)
@@ -149,51 +295,24 @@ impl SparseHistogram {
     ///
     /// The results will be sorted by the percentile.
     pub fn percentiles(&self, percentiles: &[f64]) -> Result<Option<Vec<(f64, Bucket)>>, Error> {
-        // validate all the percentiles
-        if percentiles.is_empty() {
-            return Err(Error::InvalidPercentile);
-        }
+        let mut result = Vec::with_capacity(percentiles.len());
 
-        for percentile in percentiles {
-            if !(0.0..=100.0).contains(percentile) {
-                return Err(Error::InvalidPercentile);
-            }
-        }
-
-        let total: u128 = self.count.iter().map(|v| *v as u128).sum();
-
-        // empty histogram, no percentiles available
-        if total == 0 {
-            return Ok(None);
-        }
-
-        // sort the requested percentiles so we can find them in a single pass
-        let mut percentiles = percentiles.to_vec();
-        percentiles.sort_by(|a, b| a.partial_cmp(b).unwrap());
-
-        let searches: Vec<usize> = percentiles
-            .iter()
-            .map(|p| ((total as f64) * *p / 100.0).ceil() as usize)
-            .collect();
-        let mut search_idx = 0;
-        let mut result: Vec<(f64, Bucket)> = Vec::with_capacity(percentiles.len());
-
-        let mut seen: usize = 0;
-        for (idx, count) in self.index.iter().zip(self.count.iter()) {
-            seen += *count as usize;
-            while search_idx < searches.len() && seen >= searches[search_idx] {
-                result.push((
-                    percentiles[search_idx],
-                    Bucket {
-                        count: *count,
-                        range: self.config.index_to_range(*idx),
-                    },
-                ));
-                search_idx += 1;
-            }
-        }
+        Ok(self
+            .percentiles_into(percentiles, &mut result)?
+            .then_some(result))
+    }
 
-        Ok(Some(result))
+    /// Finds the buckets for a collection of percentiles and writes them into
+    /// the provided buffer, replacing its contents. See
+    /// [`Histogram::percentiles_into`] for details.
+    pub fn percentiles_into(
+        &self,
+        percentiles: &[f64],
+        buffer: &mut Vec<(f64, Bucket)>,
+    ) -> Result<bool, Error> {
+        let buckets = self.index.iter().copied().zip(self.count.iter().copied());
+
+        percentiles_into(&self.config, percentiles, buckets, buffer)
     }
 
     /// Return a single percentile from this histogram.
@@ -205,6 +324,118 @@ impl SparseHistogram {
             .map(|v| v.map(|x| x.first().unwrap().1.clone()))
     }
 
+    /// Return a collection of estimated values for the percentiles from this
+    /// histogram, using the provided [`crate::Interpolation`] strategy to pick
+    /// a value within the bucket containing each percentile.
+    ///
+    /// Each percentile should be in the inclusive range `0.0..=100.0`. The
+    /// results will be sorted by the percentile.
+    pub fn percentile_values(
+        &self,
+        percentiles: &[f64],
+        interpolation: Interpolation,
+    ) -> Result<Option<Vec<(f64, f64)>>, Error> {
+        let buckets = self.index.iter().copied().zip(self.count.iter().copied());
+
+        Ok(ranks(percentiles, buckets)?.map(|ranks| {
+            ranks
+                .iter()
+                .map(|rank| (rank.percentile, interpolation.value(&self.config, rank)))
+                .collect()
+        }))
+    }
+
+    /// Return an estimated value for a single percentile from this histogram,
+    /// using the provided [`crate::Interpolation`] strategy.
+    ///
+    /// The percentile should be in the inclusive range `0.0..=100.0`.
+    pub fn percentile_value(
+        &self,
+        percentile: f64,
+        interpolation: Interpolation,
+    ) -> Result<Option<f64>, Error> {
+        self.percentile_values(&[percentile], interpolation)
+            .map(|v| v.map(|x| x[0].1))
+    }
+
+    /// Returns summary statistics for this histogram, such as the total count
+    /// and the approximate mean. See [`crate::Statistics`] for details.
+    ///
+    /// Only the non-zero buckets are visited.
+    pub fn statistics(&self) -> Statistics {
+        let buckets = self.index.iter().copied().zip(self.count.iter().copied());
+        Statistics::from_buckets(&self.config, buckets)
+    }
+
+    /// Returns the fraction of observations, in the range `0.0..=1.0`, which
+    /// are in buckets that lie entirely below the value. Returns `None` if the
+    /// histogram is empty.
+    ///
+    /// This is exact when the value is the lower bound of a bucket. Otherwise,
+    /// the bucket containing the value is excluded, so the result is never an
+    /// overestimate.
+    pub fn fraction_below(&self, value: u64) -> Option<f64> {
+        let (below, total) = count_below(
+            &self.config,
+            self.index.iter().copied().zip(self.count.iter().copied()),
+            value,
+        );
+        (total != 0).then(|| below as f64 / total as f64)
+    }
+
+    /// Returns the number of observations in buckets which overlap the range.
+    ///
+    /// This is exact when the range starts at the lower bound of a bucket and
+    /// ends at the upper bound of a bucket. Otherwise, the buckets at either
+    /// end are included in full, so the result is never an underestimate. An
+    /// empty range, which starts after it ends, contains no observations.
+    pub fn count_in_range(&self, range: RangeInclusive<u64>) -> u128 {
+        count_in_range(
+            &self.config,
+            self.index.iter().copied().zip(self.count.iter().copied()),
+            range,
+        )
+    }
+
+    /// Returns an iterator across the non-empty buckets which reports the
+    /// cumulative count of observations, which is the cumulative distribution.
+    pub fn cumulative(&self) -> Cumulative<'_> {
+        Cumulative::sparse(self.config, &self.index, &self.count)
+    }
+
+    /// Returns a text report of the percentile distribution in the layout of
+    /// HdrHistogram's `outputPercentileDistribution`, with one row for each
+    /// non-empty bucket and a bar chart of the bucket counts.
+    ///
+    /// The report can be read back with
+    /// [`parse_percentile_distribution`](SparseHistogram::parse_percentile_distribution).
+    pub fn percentile_distribution(&self) -> String {
+        distribution::write(
+            &self.config,
+            self.index.iter().copied().zip(self.count.iter().copied()),
+            &self.statistics(),
+            None,
+        )
+    }
+
+    /// Parses a text report, as produced by
+    /// [`percentile_distribution`](SparseHistogram::percentile_distribution), into a
+    /// histogram.
+    ///
+    /// Reports produced by HdrHistogram itself are not supported, since the
+    /// footer which describes its buckets has a different meaning. An error is
+    /// returned if the report is malformed.
+    pub fn parse_percentile_distribution(report: &str) -> Result<Self, Error> {
+        let (config, buckets) = distribution::parse(report)?;
+
+        let mut histogram = SparseHistogram::with_config(&config);
+        for (index, count) in buckets {
+            histogram.add_bucket(index, count);
+        }
+
+        Ok(histogram)
+    }
+
     /// Returns a new histogram with a reduced grouping power. The reduced
     /// grouping power should lie in the range (0..existing grouping power).
     ///
@@ -213,7 +444,14 @@ impl SparseHistogram {
     /// do not know the exact values of the data points (only that they lie
     /// within the bucket's range), it does not matter since the bucket is
     /// not split during downsampling and any value can be used.
+    ///
+    /// Histograms with a [`Config::logarithmic`] configuration cannot be
+    /// downsampled, use [`rebucket`](SparseHistogram::rebucket) instead.
     pub fn downsample(&self, grouping_power: u8) -> Result<SparseHistogram, Error> {
+        if self.config.relative_accuracy().is_some() {
+            return Err(Error::IncompatibleParameters);
+        }
+
         if grouping_power >= self.config.grouping_power() {
             return Err(Error::MaxPowerTooLow);
         }
@@ -233,9 +471,10 @@ impl SparseHistogram {
         for (idx, n) in self.index.iter().zip(self.count.iter()) {
             let new_idx = config.value_to_index(self.config.index_to_lower_bound(*idx))?;
 
-            // If it maps to the currently aggregating bucket, merge counts
+            // If it maps to the currently aggregating bucket, merge counts,
+            // wrapping on overflow as `Histogram::downsample` does
             if new_idx == aggregating_idx {
-                aggregating_count += n;
+                aggregating_count = aggregating_count.wrapping_add(*n);
                 continue;
             }
 
@@ -252,6 +491,40 @@ impl SparseHistogram {
 
         Ok(histogram)
     }
+
+    /// Returns a new histogram with the provided config, which may change both
+    /// the grouping power and the max value power, along with a report of how
+    /// the observations were carried over.
+    ///
+    /// Buckets which span several of the new buckets, such as when increasing
+    /// the grouping power, have their counts spread across them in proportion
+    /// to the width of each overlap. Observations above the new max value are
+    /// handled according to the [`crate::Overflow`] policy.
+    pub fn rebucket(
+        &self,
+        config: &Config,
+        overflow: Overflow,
+    ) -> Result<(SparseHistogram, Rebucketing), Error> {
+        let mut histogram = SparseHistogram::with_config(config);
+
+        // Buckets are produced in order of index, but consecutive buckets may
+        // map to the same new bucket and must be aggregated
+        let buckets = self.index.iter().copied().zip(self.count.iter().copied());
+        let report = rebucket(&self.config, config, buckets, overflow, |index, count| {
+            if count == 0 {
+                return;
+            }
+            match histogram.index.last() {
+                Some(last) if *last == index => {
+                    let last = histogram.count.len() - 1;
+                    histogram.count[last] = histogram.count[last].wrapping_add(count);
+                }
+                _ => histogram.add_bucket(index, count),
+            }
+        })?;
+
+        Ok((histogram, report))
+    }
 }
 
 impl<'a> IntoIterator for &'a SparseHistogram {
@@ -294,23 +567,14 @@ impl<'a> Iterator for Iter<'a> {
     }
 }
 
+/// Only the buckets are converted. A sparse histogram does not track
+/// [`crate::Exact`] values, so they are dropped, and converting back gives a
+/// histogram without them.
 impl From<&Histogram> for SparseHistogram {
     fn from(histogram: &Histogram) -> Self {
-        let mut index = Vec::new();
-        let mut count = Vec::new();
-
-        for (idx, n) in histogram.as_slice().iter().enumerate() {
-            if *n > 0 {
-                index.push(idx);
-                count.push(*n);
-            }
-        }
-
-        Self {
-            config: histogram.config(),
-            index,
-            count,
-        }
+        let mut sparse = Self::with_config(&histogram.config());
+        sparse.copy_from(histogram);
+        sparse
     }
 }
 
@@ -357,6 +621,144 @@ mod tests {
         assert_eq!(h.count, vec![6, 5, 19, 7, 3, 15, 6]);
     }
 
+    #[test]
+    // Tests that sparse and dense histograms agree on the distribution
+    fn cumulative() {
+        let mut histogram = Histogram::new(2, 16).unwrap();
+        for v in [1, 1, 5, 50, 51, 52, 1000, 1000] {
+            histogram.increment(v).unwrap();
+        }
+        let sparse = SparseHistogram::from(&histogram);
+
+        assert!(sparse.cumulative().eq(histogram.cumulative()));
+        assert_eq!(sparse.fraction_below(48), histogram.fraction_below(48));
+        assert_eq!(sparse.count_in_range(0..=5), 3);
+        assert_eq!(sparse.count_in_range(RangeInclusive::new(5, 0)), 0);
+        assert_eq!(SparseHistogram::new(2, 16).unwrap().fraction_below(5), None);
+    }
+
+    #[test]
+    // Tests that the text report can be read back
+    fn percentile_distribution() {
+        let mut histogram = Histogram::new(7, 32).unwrap();
+        for v in 1..1000 {
+            histogram.add(v * v, v).unwrap();
+        }
+
+        let sparse = SparseHistogram::from(&histogram);
+        let report = sparse.percentile_distribution();
+        assert_eq!(report, histogram.percentile_distribution());
+        assert_eq!(
+            SparseHistogram::parse_percentile_distribution(&report),
+            Ok(sparse)
+        );
+    }
+
+    #[test]
+    fn checked_add() {
+        let config = Config::new(7, 32).unwrap();
+
+        let h1 = SparseHistogram {
+            config,
+            index: vec![1, 3, 5],
+            count: vec![6, 12, 7],
+        };
+
+        let h2 = SparseHistogram {
+            config,
+            index: vec![2, 3],
+            count: vec![5, u64::MAX],
+        };
+
+        let h = h1.checked_add(&SparseHistogram::new(6, 16).unwrap());
+        assert_eq!(h, Err(Error::IncompatibleParameters));
+
+        let h = h1.checked_add(&h1).unwrap();
+        assert_eq!(h.index, vec![1, 3, 5]);
+        assert_eq!(h.count, vec![12, 24, 14]);
+
+        assert_eq!(h1.checked_add(&h2), Err(Error::Overflow));
+
+        let h = h1.saturating_add(&h2).unwrap();
+        assert_eq!(h.index, vec![1, 2, 3, 5]);
+        assert_eq!(h.count, vec![6, 5, u64::MAX, 7]);
+
+        let h = h1.wrapping_add(&h2).unwrap();
+        assert_eq!(h.index, vec![1, 2, 3, 5]);
+        assert_eq!(h.count, vec![6, 5, 11, 7]);
+    }
+
+    #[test]
+    fn wrapping_sub() {
+        let config = Config::new(7, 32).unwrap();
+
+        let h1 = SparseHistogram {
+            config,
+            index: vec![1, 3, 5],
+            count: vec![6, 12, 7],
+        };
+
+        let h2 = SparseHistogram {
+            config,
+            index: vec![2, 3, 5],
+            count: vec![1, 13, 7],
+        };
+
+        let h = h1.wrapping_sub(&SparseHistogram::new(6, 16).unwrap());
+        assert_eq!(h, Err(Error::IncompatibleParameters));
+
+        let h = h1.wrapping_sub(&h2).unwrap();
+        assert_eq!(h.index, vec![1, 2, 3]);
+        assert_eq!(h.count, vec![6, u64::MAX, u64::MAX]);
+
+        let h = h1.saturating_sub(&h2).unwrap();
+        assert_eq!(h.index, vec![1]);
+        assert_eq!(h.count, vec![6]);
+    }
+
+    #[test]
+    fn sum() {
+        let config = Config::new(7, 32).unwrap();
+
+        let h1 = SparseHistogram {
+            config,
+            index: vec![1, 3, 5],
+            count: vec![6, 12, 7],
+        };
+        let h2 = SparseHistogram::with_config(&config);
+        let h3 = SparseHistogram {
+            config,
+            index: vec![2, 3, 6, 11, 13],
+            count: vec![5, 7, 3, 15, 6],
+        };
+        let h4 = SparseHistogram {
+            config,
+            index: vec![0, 13],
+            count: vec![1, u64::MAX],
+        };
+
+        let h = SparseHistogram::checked_sum(&config, &[]).unwrap();
+        assert_eq!(h, h2);
+
+        let h = SparseHistogram::checked_sum(&config, &[&h1, &h2, &h3]).unwrap();
+        assert_eq!(h, h1.checked_add(&h3).unwrap());
+
+        assert_eq!(
+            SparseHistogram::checked_sum(&config, &[&h1, &h3, &h4]),
+            Err(Error::Overflow)
+        );
+
+        let h = SparseHistogram::wrapping_sum(&config, &[&h1, &h3, &h4]).unwrap();
+        assert_eq!(h.index, vec![0, 1, 2, 3, 5, 6, 11, 13]);
+        assert_eq!(h.count, vec![1, 6, 5, 19, 7, 3, 15, 5]);
+
+        let hdiff = SparseHistogram::new(6, 16).unwrap();
+        assert_eq!(
+            SparseHistogram::checked_sum(&config, &[&h1, &hdiff]),
+            Err(Error::IncompatibleParameters)
+        );
+    }
+
     #[test]
     fn checked_sub() {
         let config = Config::new(7, 32).unwrap();
@@ -442,6 +844,45 @@ mod tests {
         );
     }
 
+    #[test]
+    fn percentile_values() {
+        let mut hstandard = Histogram::new(4, 10).unwrap();
+
+        for v in 1..1024 {
+            let _ = hstandard.increment(v);
+        }
+
+        let hsparse = SparseHistogram::from(&hstandard);
+        let percentiles = [0.0, 1.0, 10.0, 25.0, 50.0, 75.0, 90.0, 99.0, 99.9, 100.0];
+        for interpolation in [
+            Interpolation::Lower,
+            Interpolation::Upper,
+            Interpolation::Midpoint,
+            Interpolation::Linear,
+        ] {
+            assert_eq!(
+                hstandard.percentile_values(&percentiles, interpolation),
+                hsparse.percentile_values(&percentiles, interpolation)
+            );
+        }
+    }
+
+    #[test]
+    fn statistics() {
+        let mut hstandard = Histogram::new(4, 10).unwrap();
+        assert_eq!(
+            SparseHistogram::from(&hstandard).statistics(),
+            hstandard.statistics()
+        );
+
+        for v in 1..1024 {
+            let _ = hstandard.add(v, v);
+        }
+
+        let hsparse = SparseHistogram::from(&hstandard);
+        assert_eq!(hsparse.statistics(), hstandard.statistics());
+    }
+
     fn compare_histograms(hstandard: &Histogram, hsparse: &SparseHistogram) {
         assert_eq!(hstandard.config(), hsparse.config);
 
@@ -493,5 +934,34 @@ mod tests {
             let h2 = hsparse.downsample(reduced_gp).unwrap();
             compare_histograms(&h1, &h2);
         }
+
+        // merged buckets wrap on overflow, as for the standard histogram
+        let mut histogram = Histogram::new(1, 8).unwrap();
+        histogram.add(128, u64::MAX).unwrap();
+        histogram.add(192, 2).unwrap();
+        let h1 = histogram.downsample(0).unwrap();
+        let h2 = SparseHistogram::from(&histogram).downsample(0).unwrap();
+        assert_eq!(h2, SparseHistogram::from(&h1));
+        assert_eq!(h2.count, vec![1]);
+    }
+
+    #[test]
+    // Tests that copying from a histogram reuses the storage
+    fn copy_from() {
+        let mut histogram = Histogram::new(7, 32).unwrap();
+        let mut sparse = SparseHistogram::new(2, 8).unwrap();
+        sparse.add_bucket(3, 5);
+
+        histogram.add(100, 2).unwrap();
+        histogram.add(1000, 3).unwrap();
+
+        sparse.copy_from(&histogram);
+        assert_eq!(sparse, SparseHistogram::from(&histogram));
+        assert_eq!(sparse.count, vec![2, 3]);
+
+        let capacity = sparse.index.capacity();
+        sparse.copy_from(&Histogram::new(7, 32).unwrap());
+        assert!(sparse.index.is_empty());
+        assert_eq!(sparse.index.capacity(), capacity);
     }
 }
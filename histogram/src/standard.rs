@@ -1,11 +1,28 @@
-use crate::{Bucket, Config, Error, SparseHistogram};
+use crate::cdf::{count_below, count_in_range, Cumulative};
+use crate::distribution;
+use crate::percentile::{percentiles_into, ranks};
+use crate::rebucket::rebucket;
+use crate::{
+    Bucket, Config, Error, Exact, Interpolation, Overflow, Rebucketing, SparseHistogram, Statistics,
+};
+use core::ops::RangeInclusive;
 
 /// A histogram that uses plain 64bit counters for each bucket.
+///
+/// Two histograms are equal if they have the same configuration, the same
+/// bucket counts, and the same [`crate::Exact`] values, so a histogram which
+/// tracks exact values is not equal to one with the same buckets which does
+/// not.
 #[derive(Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Histogram {
     pub(crate) config: Config,
     pub(crate) buckets: Box<[u64]>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub(crate) exact: Option<Box<Exact>>,
 }
 
 impl Histogram {
@@ -24,6 +41,17 @@ impl Histogram {
         Self {
             config: *config,
             buckets,
+            exact: None,
+        }
+    }
+
+    /// Creates a new histogram using a provided [`crate::Config`] which also
+    /// tracks the exact sum, minimum, and maximum of recorded values. See
+    /// [`crate::Exact`] for details.
+    pub fn with_exact(config: &Config) -> Self {
+        Self {
+            exact: Some(Box::default()),
+            ..Self::with_config(config)
         }
     }
 
@@ -43,6 +71,7 @@ impl Histogram {
         Ok(Self {
             config,
             buckets: buckets.into(),
+            exact: None,
         })
     }
 
@@ -57,15 +86,27 @@ impl Histogram {
     pub fn add(&mut self, value: u64, count: u64) -> Result<(), Error> {
         let index = self.config.value_to_index(value)?;
         self.buckets[index] = self.buckets[index].wrapping_add(count);
+        if let Some(exact) = &mut self.exact {
+            exact.add(value, count);
+        }
         Ok(())
     }
 
+    /// Returns the exact sum, minimum, and maximum of the recorded values if
+    /// this histogram tracks them. See [`crate::Exact`] for details.
+    pub fn exact(&self) -> Option<Exact> {
+        self.exact.as_deref().copied()
+    }
+
     /// Get a reference to the raw counters.
     pub fn as_slice(&self) -> &[u64] {
         &self.buckets
     }
 
     /// Get a mutable reference to the raw counters.
+    ///
+    /// Changes made through the slice are not reflected in the exact values
+    /// returned by [`exact`](Histogram::exact).
     pub fn as_mut_slice(&mut self) -> &mut [u64] {
         &mut self.buckets
     }
@@ -77,60 +118,29 @@ impl Histogram {
     ///
     /// The results will be sorted by the percentile.
     pub fn percentiles(&self, percentiles: &[f64]) -> Result<Option<Vec<(f64, Bucket)>>, Error> {
-        // get the total count
-        let total_count: u128 = self.buckets.iter().map(|v| *v as u128).sum();
-
-        // sort the requested percentiles so we can find them in a single pass
-        let mut percentiles = percentiles.to_vec();
-        percentiles.sort_by(|a, b| a.partial_cmp(b).unwrap());
-
-        // validate all the percentiles
-        for percentile in &percentiles {
-            if !(0.0..=100.0).contains(percentile) {
-                return Err(Error::InvalidPercentile);
-            }
-        }
-
-        // empty histogram, no percentiles available
-        if total_count == 0 {
-            return Ok(None);
-        }
+        let mut result = Vec::with_capacity(percentiles.len());
 
-        let mut bucket_idx = 0;
-        let mut partial_sum = self.buckets[bucket_idx] as u128;
-
-        let result: Vec<(f64, Bucket)> = percentiles
-            .iter()
-            .filter_map(|percentile| {
-                let count = (percentile / 100.0 * total_count as f64).ceil() as u128;
-
-                loop {
-                    // found the matching bucket index for this percentile
-                    if partial_sum >= count {
-                        return Some((
-                            *percentile,
-                            Bucket {
-                                count: self.buckets[bucket_idx],
-                                range: self.config.index_to_range(bucket_idx),
-                            },
-                        ));
-                    }
-
-                    // check if we have reached the end of the buckets
-                    if bucket_idx == (self.buckets.len() - 1) {
-                        break;
-                    }
-
-                    // otherwise, increment the bucket index, partial sum, and loop
-                    bucket_idx += 1;
-                    partial_sum += self.buckets[bucket_idx] as u128;
-                }
-
-                None
-            })
-            .collect();
+        Ok(self
+            .percentiles_into(percentiles, &mut result)?
+            .then_some(result))
+    }
 
-        Ok(Some(result))
+    /// Finds the buckets for a collection of percentiles, as with
+    /// [`percentiles`](Histogram::percentiles), and writes them into the
+    /// provided buffer, replacing its contents. Returns false if the histogram
+    /// is empty, in which case the buffer is left empty.
+    ///
+    /// Nothing is allocated once the buffer has enough capacity, so reusing a
+    /// buffer avoids allocating for each histogram. The percentiles are only
+    /// sorted if they are not already in increasing order.
+    pub fn percentiles_into(
+        &self,
+        percentiles: &[f64],
+        buffer: &mut Vec<(f64, Bucket)>,
+    ) -> Result<bool, Error> {
+        let buckets = self.buckets.iter().copied().enumerate();
+
+        percentiles_into(&self.config, percentiles, buckets, buffer)
     }
 
     /// Return a single percentile from this histogram.
@@ -142,6 +152,123 @@ impl Histogram {
             .map(|v| v.map(|x| x.first().unwrap().1.clone()))
     }
 
+    /// Return a collection of estimated values for the percentiles from this
+    /// histogram, using the provided [`crate::Interpolation`] strategy to pick
+    /// a value within the bucket containing each percentile.
+    ///
+    /// Each percentile should be in the inclusive range `0.0..=100.0`. The
+    /// results will be sorted by the percentile.
+    pub fn percentile_values(
+        &self,
+        percentiles: &[f64],
+        interpolation: Interpolation,
+    ) -> Result<Option<Vec<(f64, f64)>>, Error> {
+        let buckets = self.buckets.iter().copied().enumerate();
+
+        Ok(ranks(percentiles, buckets)?.map(|ranks| {
+            ranks
+                .iter()
+                .map(|rank| (rank.percentile, interpolation.value(&self.config, rank)))
+                .collect()
+        }))
+    }
+
+    /// Return an estimated value for a single percentile from this histogram,
+    /// using the provided [`crate::Interpolation`] strategy.
+    ///
+    /// The percentile should be in the inclusive range `0.0..=100.0`.
+    pub fn percentile_value(
+        &self,
+        percentile: f64,
+        interpolation: Interpolation,
+    ) -> Result<Option<f64>, Error> {
+        self.percentile_values(&[percentile], interpolation)
+            .map(|v| v.map(|x| x[0].1))
+    }
+
+    /// Returns summary statistics for this histogram, such as the total count
+    /// and the approximate mean. See [`crate::Statistics`] for details.
+    ///
+    /// If this histogram tracks exact values, the sum and mean are exact.
+    pub fn statistics(&self) -> Statistics {
+        let statistics =
+            Statistics::from_buckets(&self.config, self.buckets.iter().copied().enumerate());
+
+        match &self.exact {
+            Some(exact) => statistics.with_exact(exact),
+            None => statistics,
+        }
+    }
+
+    /// Returns the fraction of observations, in the range `0.0..=1.0`, which
+    /// are in buckets that lie entirely below the value. Returns `None` if the
+    /// histogram is empty.
+    ///
+    /// This is exact when the value is the lower bound of a bucket. Otherwise,
+    /// the bucket containing the value is excluded, so the result is never an
+    /// overestimate.
+    pub fn fraction_below(&self, value: u64) -> Option<f64> {
+        let (below, total) = count_below(
+            &self.config,
+            self.buckets.iter().copied().enumerate(),
+            value,
+        );
+        (total != 0).then(|| below as f64 / total as f64)
+    }
+
+    /// Returns the number of observations in buckets which overlap the range.
+    ///
+    /// This is exact when the range starts at the lower bound of a bucket and
+    /// ends at the upper bound of a bucket. Otherwise, the buckets at either
+    /// end are included in full, so the result is never an underestimate. An
+    /// empty range, which starts after it ends, contains no observations.
+    pub fn count_in_range(&self, range: RangeInclusive<u64>) -> u128 {
+        count_in_range(
+            &self.config,
+            self.buckets.iter().copied().enumerate(),
+            range,
+        )
+    }
+
+    /// Returns an iterator across the non-empty buckets which reports the
+    /// cumulative count of observations, which is the cumulative distribution.
+    pub fn cumulative(&self) -> Cumulative<'_> {
+        Cumulative::dense(self.config, &self.buckets)
+    }
+
+    /// Returns a text report of the percentile distribution in the layout of
+    /// HdrHistogram's `outputPercentileDistribution`, with one row for each
+    /// non-empty bucket and a bar chart of the bucket counts.
+    ///
+    /// The report can be read back with
+    /// [`parse_percentile_distribution`](Histogram::parse_percentile_distribution).
+    pub fn percentile_distribution(&self) -> String {
+        distribution::write(
+            &self.config,
+            self.buckets.iter().copied().enumerate(),
+            &self.statistics(),
+            self.exact().and_then(|exact| exact.max()),
+        )
+    }
+
+    /// Parses a text report, as produced by
+    /// [`percentile_distribution`](Histogram::percentile_distribution), into a
+    /// histogram.
+    ///
+    /// Reports produced by HdrHistogram itself are not supported, since the
+    /// footer which describes its buckets has a different meaning. An error is
+    /// returned if the report is malformed.
+    pub fn parse_percentile_distribution(report: &str) -> Result<Self, Error> {
+        let (config, buckets) = distribution::parse(report)?;
+
+        let mut histogram = Histogram::with_config(&config);
+        for (index, count) in buckets {
+            histogram.buckets[index] = count;
+        }
+
+        Ok(histogram)
+    }
+
     /// Returns a new histogram with a reduced grouping power. The reduced
     /// grouping power should lie in the range (0..existing grouping power).
     ///
@@ -155,7 +282,14 @@ impl Histogram {
     /// do not know the exact values of the data points (only that they lie
     /// within the bucket's range), it does not matter since the bucket is
     /// not split during downsampling and any value can be used.
+    ///
+    /// Histograms with a [`Config::logarithmic`] configuration cannot be
+    /// downsampled, use [`rebucket`](Histogram::rebucket) instead.
     pub fn downsample(&self, grouping_power: u8) -> Result<Histogram, Error> {
+        if self.config.relative_accuracy().is_some() {
+            return Err(Error::IncompatibleParameters);
+        }
+
         if grouping_power >= self.config.grouping_power() {
             return Err(Error::MaxPowerTooLow);
         }
@@ -169,14 +303,49 @@ impl Histogram {
             }
         }
 
+        // the recorded values are unchanged by downsampling
+        histogram.exact = self.exact.clone();
+
         Ok(histogram)
     }
 
+    /// Returns a new histogram with the provided config, which may change both
+    /// the grouping power and the max value power, along with a report of how
+    /// the observations were carried over.
+    ///
+    /// Buckets which span several of the new buckets, such as when increasing
+    /// the grouping power, have their counts spread across them in proportion
+    /// to the width of each overlap. Observations above the new max value are
+    /// handled according to the [`crate::Overflow`] policy.
+    ///
+    /// Exact values are kept only if no observations were clamped or
+    /// discarded.
+    pub fn rebucket(
+        &self,
+        config: &Config,
+        overflow: Overflow,
+    ) -> Result<(Histogram, Rebucketing), Error> {
+        let mut histogram = Histogram::with_config(config);
+
+        let buckets = self.as_slice().iter().copied().enumerate();
+        let report = rebucket(&self.config, config, buckets, overflow, |index, count| {
+            histogram.buckets[index] = histogram.buckets[index].wrapping_add(count);
+        })?;
+
+        if report.is_lossless() {
+            histogram.exact = self.exact.clone();
+        }
+
+        Ok((histogram, report))
+    }
+
     /// Adds the other histogram to this histogram and returns the result as a
     /// new histogram.
     ///
     /// An error is returned if the two histograms have incompatible parameters
     /// or if there is an overflow.
+    ///
+    /// Exact values are kept only if both histograms track them.
     pub fn checked_add(&self, other: &Histogram) -> Result<Histogram, Error> {
         if self.config != other.config {
             return Err(Error::IncompatibleParameters);
@@ -188,6 +357,13 @@ impl Histogram {
             *this = this.checked_add(*other).ok_or(Error::Overflow)?;
         }
 
+        result.exact = match (&self.exact, &other.exact) {
+            (Some(this), Some(other)) => {
+                Some(Box::new(this.checked_add(other).ok_or(Error::Overflow)?))
+            }
+            _ => None,
+        };
+
         Ok(result)
     }
 
@@ -195,6 +371,8 @@ impl Histogram {
     /// new histogram.
     ///
     /// An error is returned if the two histograms have incompatible parameters.
+    ///
+    /// Exact values are kept only if both histograms track them.
     pub fn wrapping_add(&self, other: &Histogram) -> Result<Histogram, Error> {
         if self.config != other.config {
             return Err(Error::IncompatibleParameters);
@@ -206,6 +384,11 @@ impl Histogram {
             *this = this.wrapping_add(*other);
         }
 
+        result.exact = match (&self.exact, &other.exact) {
+            (Some(this), Some(other)) => Some(Box::new(this.wrapping_add(other))),
+            _ => None,
+        };
+
         Ok(result)
     }
 
@@ -214,6 +397,9 @@ impl Histogram {
     ///
     /// An error is returned if the two histograms have incompatible parameters
     /// or if there is an overflow.
+    ///
+    /// Exact values are kept only if both histograms track them. The exact
+    /// minimum and maximum are no longer known if any values were removed.
     pub fn checked_sub(&self, other: &Histogram) -> Result<Histogram, Error> {
         if self.config != other.config {
             return Err(Error::IncompatibleParameters);
@@ -225,6 +411,13 @@ impl Histogram {
             *this = this.checked_sub(*other).ok_or(Error::Overflow)?;
         }
 
+        result.exact = match (&self.exact, &other.exact) {
+            (Some(this), Some(other)) => {
+                Some(Box::new(this.checked_sub(other).ok_or(Error::Overflow)?))
+            }
+            _ => None,
+        };
+
         Ok(result)
     }
 
@@ -232,6 +425,9 @@ impl Histogram {
     /// as a new histogram.
     ///
     /// An error is returned if the two histograms have incompatible parameters.
+    ///
+    /// Exact values are kept only if both histograms track them. The exact
+    /// minimum and maximum are no longer known if any values were removed.
     pub fn wrapping_sub(&self, other: &Histogram) -> Result<Histogram, Error> {
         if self.config != other.config {
             return Err(Error::IncompatibleParameters);
@@ -243,11 +439,16 @@ impl Histogram {
             *this = this.wrapping_sub(*other);
         }
 
+        result.exact = match (&self.exact, &other.exact) {
+            (Some(this), Some(other)) => Some(Box::new(this.wrapping_sub(other))),
+            _ => None,
+        };
+
         Ok(result)
     }
 
     /// Returns an interator across the histogram.
-    pub fn iter(&self) -> Iter {
+    pub fn iter(&self) -> Iter<'_> {
         Iter {
             index: 0,
             histogram: self,
@@ -297,6 +498,8 @@ impl Iterator for Iter<'_> {
     }
 }
 
+/// A [`SparseHistogram`] does not track exact values, so the histogram does
+/// not either.
 impl From<&SparseHistogram> for Histogram {
     fn from(other: &SparseHistogram) -> Self {
         let mut histogram = Histogram::with_config(&other.config);
@@ -317,7 +520,7 @@ mod tests {
     #[cfg(target_pointer_width = "64")]
     #[test]
     fn size() {
-        assert_eq!(std::mem::size_of::<Histogram>(), 48);
+        assert_eq!(std::mem::size_of::<Histogram>(), 56);
     }
 
     #[test]
@@ -384,6 +587,222 @@ mod tests {
         );
     }
 
+    #[test]
+    // Tests summary statistics
+    fn statistics() {
+        let mut histogram = Histogram::new(7, 64).unwrap();
+        assert_eq!(histogram.statistics().count(), 0);
+
+        for i in 1..=100 {
+            let _ = histogram.increment(i);
+        }
+        let _ = histogram.increment(1024);
+
+        let statistics = histogram.statistics();
+        assert_eq!(statistics.count(), 101);
+        assert_eq!(statistics.sum(), 5050.0 + 1027.5);
+        assert_eq!(statistics.min().map(|b| b.range()), Some(1..=1));
+        assert_eq!(statistics.max().map(|b| b.range()), Some(1024..=1031));
+    }
+
+    #[test]
+    // Tests exact tracking through arithmetic
+    fn exact() {
+        let config = Config::new(2, 16).unwrap();
+        let mut a = Histogram::with_exact(&config);
+        let mut b = Histogram::with_exact(&config);
+        let c = Histogram::with_config(&config);
+
+        assert_eq!(c.exact(), None);
+
+        a.add(100, 2).unwrap();
+        b.increment(3).unwrap();
+
+        let sum = a.checked_add(&b).unwrap();
+        assert_eq!(
+            sum.exact().map(|e| (e.sum(), e.min(), e.max())),
+            Some((203, Some(3), Some(100)))
+        );
+        assert_eq!(sum.statistics().mean(), Some(203.0 / 3.0));
+        assert_eq!(sum.downsample(1).unwrap().exact(), sum.exact());
+        assert_eq!(a.checked_add(&c).unwrap().exact(), None);
+
+        let difference = sum.checked_sub(&b).unwrap();
+        assert_eq!(
+            difference.exact().map(|e| (e.sum(), e.min())),
+            Some((200, None))
+        );
+
+        // sparse histograms only hold the buckets, and equality includes the
+        // exact values
+        let round_trip = Histogram::from(&SparseHistogram::from(&sum));
+        assert_eq!(round_trip.exact(), None);
+        assert_eq!(round_trip.as_slice(), sum.as_slice());
+        assert_ne!(round_trip, sum);
+    }
+
+    #[test]
+    // Tests re-bucketing into coarser, finer, and narrower configs
+    fn rebucket() {
+        let config = Config::new(7, 32).unwrap();
+        let mut histogram = Histogram::with_exact(&config);
+        for v in 1..=1000 {
+            histogram.increment(v).unwrap();
+        }
+
+        // reducing the grouping power is the same as downsampling
+        let coarse = Config::new(4, 32).unwrap();
+        let (h, report) = histogram.rebucket(&coarse, Overflow::Error).unwrap();
+        assert_eq!(h, histogram.downsample(4).unwrap());
+        assert_eq!(report.exact(), 1000);
+        assert_eq!(h.exact(), histogram.exact());
+
+        // increasing the grouping power and back again is lossless
+        let fine = Config::new(10, 40).unwrap();
+        let (h2, report) = h.rebucket(&fine, Overflow::Error).unwrap();
+        assert_eq!(h2.statistics().count(), 1000);
+        assert!(report.approximated() > 0);
+        assert_eq!(h2.rebucket(&coarse, Overflow::Error).unwrap().0, h);
+
+        // reducing the max value power
+        let narrow = Config::new(7, 9).unwrap();
+        assert_eq!(
+            histogram.rebucket(&narrow, Overflow::Error),
+            Err(Error::OutOfRange)
+        );
+        let (h, report) = histogram.rebucket(&narrow, Overflow::Discard).unwrap();
+        assert_eq!(report.discarded(), 1000 - 511);
+        assert_eq!(h.statistics().count(), 511);
+        assert_eq!(h.exact(), None);
+
+        let sparse = SparseHistogram::from(&histogram);
+        let (s, report) = sparse.rebucket(&narrow, Overflow::Clamp).unwrap();
+        assert_eq!(report.clamped(), 1000 - 511);
+        assert_eq!(
+            s,
+            SparseHistogram::from(&histogram.rebucket(&narrow, Overflow::Clamp).unwrap().0)
+        );
+    }
+
+    #[test]
+    // Tests the cumulative distribution and rank queries
+    fn cumulative() {
+        let mut histogram = Histogram::new(2, 16).unwrap();
+        assert_eq!(histogram.fraction_below(10), None);
+        assert_eq!(histogram.cumulative().count(), 0);
+
+        for v in 1..=100 {
+            histogram.increment(v).unwrap();
+        }
+
+        // 48 and 64 are bucket boundaries, 50 is within the bucket 48..=55
+        assert_eq!(histogram.fraction_below(48), Some(0.47));
+        assert_eq!(histogram.fraction_below(50), Some(0.47));
+        assert_eq!(histogram.fraction_below(64), Some(0.63));
+        assert_eq!(histogram.fraction_below(1000), Some(1.0));
+
+        assert_eq!(histogram.count_in_range(48..=63), 16);
+        assert_eq!(histogram.count_in_range(50..=50), 8);
+        assert_eq!(histogram.count_in_range(101..=1000), 5);
+        assert_eq!(histogram.count_in_range(112..=1000), 0);
+
+        // a reversed range is empty, even within a single bucket
+        assert_eq!(histogram.count_in_range(RangeInclusive::new(55, 48)), 0);
+        assert_eq!(histogram.count_in_range(RangeInclusive::new(1000, 0)), 0);
+
+        let buckets: Vec<_> = histogram.cumulative().collect();
+        assert_eq!(buckets.len(), 22);
+        assert_eq!(buckets[0].range(), 1..=1);
+        assert_eq!(buckets[0].cumulative(), 1);
+        assert_eq!(buckets[21].range(), 96..=111);
+        assert_eq!(buckets[21].count(), 5);
+        assert_eq!(buckets[21].fraction(), 1.0);
+        assert!(buckets
+            .windows(2)
+            .all(|w| w[1].cumulative() == w[0].cumulative() + w[1].count() as u128));
+    }
+
+    #[test]
+    // Tests the text report of the percentile distribution
+    fn percentile_distribution() {
+        let mut histogram = Histogram::new(2, 8).unwrap();
+        histogram.add(1, 3).unwrap();
+        histogram.increment(5).unwrap();
+        histogram.add(200, 2).unwrap();
+
+        let report = histogram.percentile_distribution();
+        let expected = "       Value     Percentile TotalCount 1/(1-Percentile)
+
+       1.000 0.500000000000          3           2.00 |########################################
+       5.000 0.666666666667          4           3.00 |##############
+     223.000 1.000000000000          6            inf |###########################
+#[Mean    =       70.500, StdDeviation   =       96.884]
+#[Max     =      223.000, Total count    =            6]
+#[Buckets =           28, SubBuckets     =            4]
+";
+        assert_eq!(report, expected);
+
+        assert_eq!(
+            Histogram::parse_percentile_distribution(&report),
+            Ok(histogram)
+        );
+        assert_eq!(
+            Histogram::parse_percentile_distribution("Value\n1.000 0.5 3\n"),
+            Err(Error::InvalidEncoding)
+        );
+
+        // the last bucket can be read back
+        let mut histogram = Histogram::new(2, 8).unwrap();
+        histogram.increment(255).unwrap();
+        let report = histogram.percentile_distribution();
+        assert_eq!(
+            Histogram::parse_percentile_distribution(&report),
+            Ok(histogram)
+        );
+    }
+
+    #[test]
+    // Tests percentile value estimates
+    fn percentile_values() {
+        let mut histogram = Histogram::new(2, 16).unwrap();
+
+        assert_eq!(
+            histogram.percentile_value(50.0, Interpolation::Linear),
+            Ok(None)
+        );
+
+        for i in 1..=100 {
+            let _ = histogram.increment(i);
+        }
+
+        // the 50th observation is 50, which is in the bucket 48..=55
+        assert_eq!(
+            histogram.percentile_value(50.0, Interpolation::Lower),
+            Ok(Some(48.0))
+        );
+        assert_eq!(
+            histogram.percentile_value(50.0, Interpolation::Upper),
+            Ok(Some(55.0))
+        );
+        assert_eq!(
+            histogram.percentile_value(50.0, Interpolation::Midpoint),
+            Ok(Some(51.5))
+        );
+        assert_eq!(
+            histogram.percentile_value(50.0, Interpolation::Linear),
+            Ok(Some(48.0 + 7.0 * 3.0 / 8.0))
+        );
+
+        assert_eq!(
+            histogram.percentile_values(&[100.0, 0.0], Interpolation::Lower),
+            Ok(Some(vec![(0.0, 1.0), (100.0, 96.0)]))
+        );
+        assert_eq!(
+            histogram.percentile_value(101.0, Interpolation::Lower),
+            Err(Error::InvalidPercentile)
+        );
+    }
+
     #[test]
     #[ignore = "this test is flaky (see issue #100)"]
     // Tests downsampling
@@ -526,4 +945,73 @@ mod tests {
 
         assert!(constructed == histogram);
     }
+
+    #[cfg(target_has_atomic = "64")]
+    #[test]
+    // Tests percentiles with a logarithmic configuration
+    fn logarithmic() {
+        let config = Config::logarithmic(0.01, 64).unwrap();
+        let mut histogram = Histogram::with_config(&config);
+        let atomic = crate::AtomicHistogram::with_config(&config);
+
+        for v in 1..=100_000 {
+            histogram.increment(v).unwrap();
+            atomic.increment(v).unwrap();
+        }
+
+        let sparse = SparseHistogram::from(&histogram);
+
+        for (percentile, expected) in [(50.0, 50_000), (99.0, 99_000), (99.9, 99_900)] {
+            let bucket = histogram.percentile(percentile).unwrap().unwrap();
+            assert!(bucket.start() <= expected && expected <= bucket.end());
+            let midpoint = (bucket.start() + bucket.end()) as f64 / 2.0;
+            assert!((midpoint - expected as f64).abs() <= 0.01 * midpoint);
+            assert_eq!(sparse.percentile(percentile).unwrap(), Some(bucket.clone()));
+            assert_eq!(atomic.load().percentile(percentile).unwrap(), Some(bucket));
+        }
+
+        assert_eq!(histogram.downsample(4), Err(Error::IncompatibleParameters));
+    }
+
+    #[test]
+    // Tests that percentiles can be found into a reused buffer
+    fn percentiles_into() {
+        let mut histogram = Histogram::new(7, 64).unwrap();
+        let mut buffer = Vec::new();
+
+        assert_eq!(histogram.percentiles_into(&[50.0], &mut buffer), Ok(false));
+        assert!(buffer.is_empty());
+
+        for i in 1..=100 {
+            histogram.increment(i).unwrap();
+        }
+
+        for percentiles in [[25.0, 50.0, 99.0], [99.0, 25.0, 50.0]] {
+            assert_eq!(
+                histogram.percentiles_into(&percentiles, &mut buffer),
+                Ok(true)
+            );
+            assert_eq!(
+                Some(buffer.clone()),
+                histogram.percentiles(&percentiles).unwrap()
+            );
+            assert_eq!(
+                buffer.iter().map(|(_, b)| b.end()).collect::<Vec<_>>(),
+                vec![25, 50, 99]
+            );
+        }
+
+        let sparse = SparseHistogram::from(&histogram);
+        let mut sparse_buffer = Vec::with_capacity(3);
+        assert_eq!(
+            sparse.percentiles_into(&[99.0, 25.0, 50.0], &mut sparse_buffer),
+            Ok(true)
+        );
+        assert_eq!(sparse_buffer, buffer);
+
+        assert_eq!(
+            histogram.percentiles_into(&[101.0], &mut buffer),
+            Err(Error::InvalidPercentile)
+        );
+    }
 }
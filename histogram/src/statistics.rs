@@ -0,0 +1,148 @@
+use crate::{Bucket, Config, Exact};
+
+/// Summary statistics for a histogram.
+///
+/// Since a histogram only records which bucket each observation fell into, the
+/// sum, mean, and variance are approximations which treat every observation as
+/// the midpoint of its bucket. The error is bounded by the bucket widths, see
+/// [`crate::Config::error`]. Histograms which track [`crate::Exact`] values
+/// report an exact sum and mean.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Statistics {
+    count: u128,
+    sum: f64,
+    mean: f64,
+    m2: f64,
+    min: Option<Bucket>,
+    max: Option<Bucket>,
+}
+
+impl Statistics {
+    /// Computes the statistics in a single pass over the non-zero buckets,
+    /// which must be in increasing order of index.
+    pub(crate) fn from_buckets(
+        config: &Config,
+        buckets: impl Iterator<Item = (usize, u64)>,
+    ) -> Self {
+        let mut statistics = Self {
+            count: 0,
+            sum: 0.0,
+            mean: 0.0,
+            m2: 0.0,
+            min: None,
+            max: None,
+        };
+
+        let mut max: Option<(usize, u64)> = None;
+
+        for (index, count) in buckets.filter(|(_, count)| *count != 0) {
+            let lower = config.index_to_lower_bound(index) as f64;
+            let upper = config.index_to_upper_bound(index) as f64;
+            let value = (lower + upper) / 2.0;
+            let weight = count as f64;
+
+            // weighted form of Welford's online algorithm, which avoids the
+            // cancellation of the naive sum of squares approach
+            statistics.count += count as u128;
+            statistics.sum += value * weight;
+            let delta = value - statistics.mean;
+            statistics.mean += delta * weight / statistics.count as f64;
+            statistics.m2 += weight * delta * (value - statistics.mean);
+
+            if statistics.min.is_none() {
+                statistics.min = Some(Bucket {
+                    count,
+                    range: config.index_to_range(index),
+                });
+            }
+
+            max = Some((index, count));
+        }
+
+        statistics.max = max.map(|(index, count)| Bucket {
+            count,
+            range: config.index_to_range(index),
+        });
+
+        statistics
+    }
+
+    /// Replaces the approximate sum and mean with exact values. The variance is
+    /// still computed from the bucket midpoints.
+    pub(crate) fn with_exact(mut self, exact: &Exact) -> Self {
+        if self.count != 0 {
+            self.sum = exact.sum() as f64;
+            self.mean = self.sum / self.count as f64;
+        }
+        self
+    }
+
+    /// Returns the total number of observations.
+    pub fn count(&self) -> u128 {
+        self.count
+    }
+
+    /// Returns the approximate sum of all observations.
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    /// Returns the approximate mean of the observations, or `None` if there
+    /// are no observations.
+    pub fn mean(&self) -> Option<f64> {
+        (self.count != 0).then_some(self.mean)
+    }
+
+    /// Returns the approximate population variance of the observations, or
+    /// `None` if there are no observations.
+    pub fn variance(&self) -> Option<f64> {
+        (self.count != 0).then(|| self.m2 / self.count as f64)
+    }
+
+    /// Returns the approximate population standard deviation of the
+    /// observations, or `None` if there are no observations.
+    pub fn stddev(&self) -> Option<f64> {
+        self.variance().map(f64::sqrt)
+    }
+
+    /// Returns the lowest non-empty bucket, which contains the minimum
+    /// observation.
+    pub fn min(&self) -> Option<Bucket> {
+        self.min.clone()
+    }
+
+    /// Returns the highest non-empty bucket, which contains the maximum
+    /// observation.
+    pub fn max(&self) -> Option<Bucket> {
+        self.max.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    // Test statistics against exactly representable values
+    fn statistics() {
+        let config = Config::new(7, 64).unwrap();
+
+        let empty = Statistics::from_buckets(&config, [].into_iter());
+        assert_eq!(empty.count(), 0);
+        assert_eq!(empty.mean(), None);
+        assert_eq!(empty.stddev(), None);
+        assert_eq!(empty.min(), None);
+
+        // values below the cutoff are stored exactly
+        let buckets = [(2, 1), (4, 2), (5, 0), (6, 1)];
+        let statistics = Statistics::from_buckets(&config, buckets.into_iter());
+
+        assert_eq!(statistics.count(), 4);
+        assert_eq!(statistics.sum(), 16.0);
+        assert_eq!(statistics.mean(), Some(4.0));
+        assert!((statistics.variance().unwrap() - 2.0).abs() < 1e-9);
+        assert!((statistics.stddev().unwrap() - 2.0_f64.sqrt()).abs() < 1e-9);
+        assert_eq!(statistics.min().map(|b| b.range()), Some(2..=2));
+        assert_eq!(statistics.max().map(|b| b.range()), Some(6..=6));
+    }
+}
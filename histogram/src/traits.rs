@@ -0,0 +1,426 @@
+use crate::cdf::count_below;
+use crate::distance;
+use crate::percentile::percentiles_into;
+use crate::{
+    AtomicHistogram, Bucket, CompactAtomicHistogram, CompactHistogram, Config, CumulativeBucket,
+    DecayingHistogram, Error, Histogram, PagedHistogram, ShardedHistogram, SparseHistogram,
+};
+use core::sync::atomic::Ordering;
+
+/// Read access to the buckets of a histogram, which allows generic code such
+/// as exporters and reports to accept any kind of histogram.
+///
+/// Only [`counts`](ReadHistogram::counts) and
+/// [`config`](ReadHistogram::config) must be provided, the other methods are
+/// derived from them.
+///
+/// The methods have the same meaning for every implementation. In particular
+/// the percentile methods accept an empty slice of percentiles, the zeroth
+/// percentile is the first observation, and the results are sorted by the
+/// percentile.
+pub trait ReadHistogram {
+    /// Returns the bucket configuration.
+    fn config(&self) -> Config;
+
+    /// Returns the index and count of each non-empty bucket, in increasing
+    /// order of index. The index is the position of the bucket in
+    /// [`Histogram::as_slice`].
+    fn counts(&self) -> impl Iterator<Item = (usize, u64)> + Clone + '_;
+
+    /// Returns the non-empty buckets in increasing order.
+    fn buckets(&self) -> impl Iterator<Item = Bucket> + '_ {
+        let config = self.config();
+
+        self.counts().map(move |(index, count)| Bucket {
+            count,
+            range: config.index_to_range(index),
+        })
+    }
+
+    /// Returns the total number of observations.
+    fn total_count(&self) -> u128 {
+        self.counts().map(|(_, count)| count as u128).sum()
+    }
+
+    /// Return a collection of percentiles from this histogram, or `None` if it
+    /// is empty.
+    ///
+    /// Each percentile should be in the inclusive range `0.0..=100.0`. For
+    /// example, the 50th percentile (median) can be found using `50.0`.
+    fn percentiles(&self, percentiles: &[f64]) -> Result<Option<Vec<(f64, Bucket)>>, Error> {
+        let mut result = Vec::with_capacity(percentiles.len());
+
+        Ok(self
+            .percentiles_into(percentiles, &mut result)?
+            .then_some(result))
+    }
+
+    /// Finds the buckets for a collection of percentiles and writes them into
+    /// the provided buffer, replacing its contents. Returns false if the
+    /// histogram is empty. See [`Histogram::percentiles_into`] for details.
+    fn percentiles_into(
+        &self,
+        percentiles: &[f64],
+        buffer: &mut Vec<(f64, Bucket)>,
+    ) -> Result<bool, Error> {
+        percentiles_into(&self.config(), percentiles, self.counts(), buffer)
+    }
+
+    /// Return a single percentile from this histogram, or `None` if it is
+    /// empty.
+    fn percentile(&self, percentile: f64) -> Result<Option<Bucket>, Error> {
+        self.percentiles(&[percentile])
+            .map(|v| v.map(|x| x.first().unwrap().1.clone()))
+    }
+
+    /// Returns the non-empty buckets along with the running total of
+    /// observations, which is the cumulative distribution.
+    fn cumulative(&self) -> impl Iterator<Item = CumulativeBucket> + '_ {
+        let config = self.config();
+        let total = self.total_count();
+        let mut cumulative = 0;
+
+        self.counts().map(move |(index, count)| {
+            cumulative += count as u128;
+            CumulativeBucket {
+                count,
+                cumulative,
+                total,
+                range: config.index_to_range(index),
+            }
+        })
+    }
+
+    /// Returns the fraction of observations which are below the value, or
+    /// `None` if the histogram is empty. See [`Histogram::fraction_below`] for
+    /// details.
+    fn fraction_below(&self, value: u64) -> Option<f64> {
+        let (below, total) = count_below(&self.config(), self.counts(), value);
+
+        (total != 0).then(|| below as f64 / total as f64)
+    }
+
+    /// Returns the Kolmogorov-Smirnov statistic between this histogram and
+    /// another, which is the largest difference between the fractions of their
+    /// observations below any value. It is in the range `0.0..=1.0`, or `None`
+    /// if either histogram is empty.
+    ///
+    /// Histograms with different grouping powers are compared by downsampling
+    /// the finer one, other differences in the configuration are an
+    /// [`Error::IncompatibleParameters`].
+    fn kolmogorov_smirnov(&self, other: &impl ReadHistogram) -> Result<Option<f64>, Error> {
+        distance::kolmogorov_smirnov(self, other)
+    }
+
+    /// Returns the earth mover's distance between this histogram and another,
+    /// which is the mean distance each observation must be moved to turn one
+    /// distribution into the other, in the same units as the values. Each
+    /// observation is treated as the midpoint of its bucket. Returns `None` if
+    /// either histogram is empty.
+    ///
+    /// The configurations must be compatible, as for
+    /// [`kolmogorov_smirnov`](ReadHistogram::kolmogorov_smirnov).
+    fn earth_movers_distance(&self, other: &impl ReadHistogram) -> Result<Option<f64>, Error> {
+        distance::earth_movers_distance(self, other)
+    }
+
+    /// Returns the ratio of each percentile of another histogram to the same
+    /// percentile of this one, using the midpoints of the buckets, or `None`
+    /// if either histogram is empty. For example, a ratio of `1.5` for the
+    /// 99th percentile means the other histogram's p99 is 50% higher. The
+    /// results are sorted by the percentile.
+    ///
+    /// The ratio is `None` for a percentile which is zero in this histogram,
+    /// since there is no ratio to zero.
+    ///
+    /// The configurations must be compatible, as for
+    /// [`kolmogorov_smirnov`](ReadHistogram::kolmogorov_smirnov).
+    #[allow(clippy::type_complexity)]
+    fn percentile_ratios(
+        &self,
+        other: &impl ReadHistogram,
+        percentiles: &[f64],
+    ) -> Result<Option<Vec<(f64, Option<f64>)>>, Error> {
+        distance::percentile_ratios(self, other, percentiles)
+    }
+}
+
+/// Recording of observations into a histogram, which allows generic code to
+/// record into any kind of histogram.
+///
+/// Histograms with atomic counters can also record through a shared
+/// reference using their inherent methods.
+pub trait RecordHistogram {
+    /// Increment the counter for the bucket corresponding to the provided value
+    /// by some count.
+    fn add(&mut self, value: u64, count: u64) -> Result<(), Error>;
+
+    /// Increment the counter for the bucket corresponding to the provided value
+    /// by one.
+    fn increment(&mut self, value: u64) -> Result<(), Error> {
+        self.add(value, 1)
+    }
+}
+
+impl ReadHistogram for Histogram {
+    fn config(&self) -> Config {
+        self.config
+    }
+
+    fn counts(&self) -> impl Iterator<Item = (usize, u64)> + Clone + '_ {
+        self.buckets
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|(_, count)| *count != 0)
+    }
+}
+
+impl ReadHistogram for SparseHistogram {
+    fn config(&self) -> Config {
+        self.config
+    }
+
+    fn counts(&self) -> impl Iterator<Item = (usize, u64)> + Clone + '_ {
+        self.index
+            .iter()
+            .copied()
+            .zip(self.count.iter().copied())
+            .filter(|(_, count)| *count != 0)
+    }
+}
+
+/// The counters are read as the methods walk them, so they may change during
+/// a call. The percentile and cumulative methods read the counters once, into
+/// a new [`Histogram`], so that their results are consistent.
+impl ReadHistogram for AtomicHistogram {
+    fn config(&self) -> Config {
+        self.config
+    }
+
+    fn counts(&self) -> impl Iterator<Item = (usize, u64)> + Clone + '_ {
+        self.buckets
+            .iter()
+            .map(|bucket| bucket.load(Ordering::Relaxed))
+            .enumerate()
+            .filter(|(_, count)| *count != 0)
+    }
+
+    fn percentiles_into(
+        &self,
+        percentiles: &[f64],
+        buffer: &mut Vec<(f64, Bucket)>,
+    ) -> Result<bool, Error> {
+        self.load().percentiles_into(percentiles, buffer)
+    }
+
+    fn cumulative(&self) -> impl Iterator<Item = CumulativeBucket> + '_ {
+        let histogram = self.load();
+        let buckets: Vec<CumulativeBucket> = histogram.cumulative().collect();
+
+        buckets.into_iter()
+    }
+}
+
+/// As with the [`AtomicHistogram`], the percentile and cumulative methods
+/// read the counters once so that their results are consistent.
+impl ReadHistogram for PagedHistogram {
+    fn config(&self) -> Config {
+        self.config()
+    }
+
+    fn counts(&self) -> impl Iterator<Item = (usize, u64)> + Clone + '_ {
+        self.counters()
+            .map(|(index, counter)| (index, counter.load(Ordering::Relaxed)))
+            .filter(|(_, count)| *count != 0)
+    }
+
+    fn percentiles_into(
+        &self,
+        percentiles: &[f64],
+        buffer: &mut Vec<(f64, Bucket)>,
+    ) -> Result<bool, Error> {
+        self.load().percentiles_into(percentiles, buffer)
+    }
+
+    fn cumulative(&self) -> impl Iterator<Item = CumulativeBucket> + '_ {
+        let histogram = self.load();
+        let buckets: Vec<CumulativeBucket> = histogram.cumulative().collect();
+
+        buckets.into_iter()
+    }
+}
+
+impl ReadHistogram for CompactHistogram {
+    fn config(&self) -> Config {
+        self.config()
+    }
+
+    fn counts(&self) -> impl Iterator<Item = (usize, u64)> + Clone + '_ {
+        (0..self.len())
+            .map(|index| (index, self.count(index)))
+            .filter(|(_, count)| *count != 0)
+    }
+}
+
+/// The narrow and overflow counters of a bucket must be summed, so each method
+/// reads all of the counters once, into a new [`Histogram`].
+impl ReadHistogram for CompactAtomicHistogram {
+    fn config(&self) -> Config {
+        self.config()
+    }
+
+    fn counts(&self) -> impl Iterator<Item = (usize, u64)> + Clone + '_ {
+        let histogram = self.load();
+        let counts: Vec<(usize, u64)> = histogram.counts().collect();
+
+        counts.into_iter()
+    }
+
+    fn percentiles_into(
+        &self,
+        percentiles: &[f64],
+        buffer: &mut Vec<(f64, Bucket)>,
+    ) -> Result<bool, Error> {
+        self.load().percentiles_into(percentiles, buffer)
+    }
+
+    fn cumulative(&self) -> impl Iterator<Item = CumulativeBucket> + '_ {
+        let histogram = self.load();
+        let buckets: Vec<CumulativeBucket> = histogram.cumulative().collect();
+
+        buckets.into_iter()
+    }
+}
+
+/// The counts, and so the buckets and cumulative counts, are the decayed
+/// weights as of the newest instant, in units of [`DecayingHistogram::SCALE`]
+/// per observation, which does not affect the percentiles. The total count is
+/// converted back to observations, rounded down, see
+/// [`DecayingHistogram::total_weight`] for the exact total. Recording requires
+/// an instant, so [`RecordHistogram`] is not implemented.
+impl ReadHistogram for DecayingHistogram {
+    fn config(&self) -> Config {
+        self.config()
+    }
+
+    fn counts(&self) -> impl Iterator<Item = (usize, u64)> + Clone + '_ {
+        self.counts().filter(|(_, count)| *count != 0)
+    }
+
+    fn total_count(&self) -> u128 {
+        self.counts().map(|(_, count)| count as u128).sum::<u128>()
+            / DecayingHistogram::SCALE as u128
+    }
+}
+
+impl RecordHistogram for Histogram {
+    fn add(&mut self, value: u64, count: u64) -> Result<(), Error> {
+        Histogram::add(self, value, count)
+    }
+}
+
+impl RecordHistogram for AtomicHistogram {
+    fn add(&mut self, value: u64, count: u64) -> Result<(), Error> {
+        AtomicHistogram::add(self, value, count)
+    }
+}
+
+impl RecordHistogram for PagedHistogram {
+    fn add(&mut self, value: u64, count: u64) -> Result<(), Error> {
+        PagedHistogram::add(self, value, count)
+    }
+}
+
+impl RecordHistogram for CompactHistogram {
+    fn add(&mut self, value: u64, count: u64) -> Result<(), Error> {
+        CompactHistogram::add(self, value, count)
+    }
+}
+
+impl RecordHistogram for CompactAtomicHistogram {
+    fn add(&mut self, value: u64, count: u64) -> Result<(), Error> {
+        CompactAtomicHistogram::add(self, value, count)
+    }
+}
+
+impl RecordHistogram for ShardedHistogram {
+    fn add(&mut self, value: u64, count: u64) -> Result<(), Error> {
+        ShardedHistogram::add(self, value, count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CounterWidth;
+
+    fn record(histogram: &mut impl RecordHistogram) {
+        for value in 1..=100 {
+            histogram.increment(value).unwrap();
+        }
+        histogram.add(1000, 5).unwrap();
+    }
+
+    fn summarize(histogram: &impl ReadHistogram) -> (u128, Vec<u64>, Option<f64>, usize) {
+        let percentiles = histogram
+            .percentiles(&[99.0, 50.0, 0.0])
+            .unwrap()
+            .unwrap()
+            .iter()
+            .map(|(_, bucket)| bucket.end())
+            .collect();
+
+        assert_eq!(histogram.percentiles(&[]), Ok(Some(vec![])));
+        assert_eq!(
+            histogram.cumulative().last().map(|b| b.cumulative()),
+            Some(histogram.total_count())
+        );
+
+        (
+            histogram.total_count(),
+            percentiles,
+            histogram.fraction_below(51),
+            histogram.buckets().count(),
+        )
+    }
+
+    #[test]
+    // Tests that every kind of histogram gives the same results
+    fn generic() {
+        let config = Config::new(7, 64).unwrap();
+
+        let mut histogram = Histogram::with_config(&config);
+        let mut atomic = AtomicHistogram::with_config(&config);
+        let mut sharded = ShardedHistogram::with_config(&config, 2).unwrap();
+        let mut paged = PagedHistogram::with_config(&config);
+        let mut compact = CompactHistogram::with_config(&config, CounterWidth::U16);
+        let mut compact_atomic = CompactAtomicHistogram::with_config(&config, CounterWidth::U16);
+
+        record(&mut histogram);
+        record(&mut atomic);
+        record(&mut sharded);
+        record(&mut paged);
+        record(&mut compact);
+        record(&mut compact_atomic);
+
+        let sparse = SparseHistogram::from(&histogram);
+
+        let expected = (105, vec![1, 53, 1003], Some(50.0 / 105.0), 101);
+        assert_eq!(summarize(&histogram), expected);
+        assert_eq!(summarize(&sparse), expected);
+        assert_eq!(summarize(&atomic), expected);
+        assert_eq!(summarize(&sharded.load()), expected);
+        assert_eq!(summarize(&paged), expected);
+        assert_eq!(summarize(&compact), expected);
+        assert_eq!(summarize(&compact_atomic), expected);
+
+        let empty = SparseHistogram::with_config(&config);
+        assert_eq!(ReadHistogram::percentile(&empty, 50.0), Ok(None));
+        assert_eq!(empty.fraction_below(10), None);
+        assert_eq!(
+            ReadHistogram::percentiles(&empty, &[101.0]),
+            Err(Error::InvalidPercentile)
+        );
+    }
+}
@@ -0,0 +1,374 @@
+use crate::{Config, Error, Histogram};
+use clocksource::precise::{Duration, Instant};
+
+/// A histogram which retains a history of observations over a moving window
+/// of time.
+///
+/// The window is divided into a ring of fixed-duration slices, each of which
+/// is a [`crate::Histogram`] sharing the same [`crate::Config`]. Recording a
+/// value at an instant beyond the newest slice advances the window, clearing
+/// any slices which fall out of it. Merged views can then be taken over the
+/// whole window or any sub-range of it.
+#[derive(Clone, Debug)]
+pub struct WindowedHistogram {
+    config: Config,
+    resolution: Duration,
+    start: Instant,
+    tick: u64,
+    slices: Box<[Histogram]>,
+}
+
+impl WindowedHistogram {
+    /// Construct a new windowed histogram from the provided parameters. See the
+    /// documentation for [`crate::Config`] to understand their meaning.
+    ///
+    /// The `span` is the total duration of history to retain and the
+    /// `resolution` is the duration covered by each slice. The span must be a
+    /// non-zero multiple of the resolution.
+    pub fn new(
+        grouping_power: u8,
+        max_value_power: u8,
+        span: Duration,
+        resolution: Duration,
+    ) -> Result<Self, Error> {
+        let config = Config::new(grouping_power, max_value_power)?;
+
+        Self::with_config(&config, span, resolution)
+    }
+
+    /// Creates a new windowed histogram using a provided [`crate::Config`]. The
+    /// window begins at the current instant.
+    pub fn with_config(
+        config: &Config,
+        span: Duration,
+        resolution: Duration,
+    ) -> Result<Self, Error> {
+        Self::with_start(config, span, resolution, Instant::now())
+    }
+
+    /// Creates a new windowed histogram using a provided [`crate::Config`] with
+    /// the first slice beginning at the provided instant.
+    pub fn with_start(
+        config: &Config,
+        span: Duration,
+        resolution: Duration,
+        start: Instant,
+    ) -> Result<Self, Error> {
+        if resolution.as_nanos() == 0
+            || span.as_nanos() < resolution.as_nanos()
+            || !span.as_nanos().is_multiple_of(resolution.as_nanos())
+        {
+            return Err(Error::IncompatibleParameters);
+        }
+
+        let count = (span.as_nanos() / resolution.as_nanos()) as usize;
+        let slices: Box<[Histogram]> = vec![Histogram::with_config(config); count].into();
+
+        Ok(Self {
+            config: *config,
+            resolution,
+            start,
+            tick: 0,
+            slices,
+        })
+    }
+
+    /// Increment the counter for the bucket corresponding to the provided value
+    /// by one in the slice containing the provided instant.
+    pub fn increment(&mut self, time: Instant, value: u64) -> Result<(), Error> {
+        self.add(time, value, 1)
+    }
+
+    /// Add some count to the counter for the bucket corresponding to the
+    /// provided value in the slice containing the provided instant.
+    ///
+    /// An error is returned if the instant is older than the retained window.
+    pub fn add(&mut self, time: Instant, value: u64, count: u64) -> Result<(), Error> {
+        let tick = self.tick_at(time).ok_or(Error::OutOfRange)?;
+
+        self.advance(tick);
+
+        if tick + self.slices.len() as u64 <= self.tick {
+            return Err(Error::OutOfRange);
+        }
+
+        let slot = self.slot(tick);
+        self.slices[slot].add(value, count)
+    }
+
+    /// Advances the window so that the newest slice contains the provided
+    /// instant. Slices which fall out of the window are cleared. Instants
+    /// which are not newer than the current slice have no effect.
+    pub fn advance_to(&mut self, time: Instant) {
+        if let Some(tick) = self.tick_at(time) {
+            self.advance(tick);
+        }
+    }
+
+    /// Returns a histogram which merges all slices overlapping the half-open
+    /// time range `start..end`. The range is clamped to the retained window.
+    ///
+    /// An error is returned if `end` is before `start`.
+    pub fn range(&self, start: Instant, end: Instant) -> Result<Histogram, Error> {
+        if end < start {
+            return Err(Error::IncompatibleTimeRange);
+        }
+
+        let mut histogram = Histogram::with_config(&self.config);
+
+        // the range ends before the window begins
+        let end = match end.checked_duration_since(self.start) {
+            Some(end) if end.as_nanos() > 0 => end.as_nanos(),
+            _ => return Ok(histogram),
+        };
+
+        let resolution = self.resolution.as_nanos();
+
+        let first = start
+            .checked_duration_since(self.start)
+            .map(|d| d.as_nanos() / resolution)
+            .unwrap_or(0)
+            .max(self.oldest());
+        let last = ((end - 1) / resolution).min(self.tick);
+
+        for tick in first..=last {
+            self.merge_into(&mut histogram, tick);
+        }
+
+        Ok(histogram)
+    }
+
+    /// Returns a histogram which merges the most recent slices covering at
+    /// least the provided duration, up to the full window.
+    pub fn last(&self, duration: Duration) -> Histogram {
+        let count = duration
+            .as_nanos()
+            .div_ceil(self.resolution.as_nanos())
+            .min(self.slices.len() as u64);
+
+        let mut histogram = Histogram::with_config(&self.config);
+
+        for tick in (self.tick + 1).saturating_sub(count)..=self.tick {
+            self.merge_into(&mut histogram, tick);
+        }
+
+        histogram
+    }
+
+    /// Returns a histogram which merges every slice in the retained window.
+    pub fn window(&self) -> Histogram {
+        self.last(self.span())
+    }
+
+    /// Returns the bucket configuration shared by every slice.
+    pub fn config(&self) -> Config {
+        self.config
+    }
+
+    /// Returns the duration covered by each slice.
+    pub fn resolution(&self) -> Duration {
+        self.resolution
+    }
+
+    /// Returns the total duration covered by the window.
+    pub fn span(&self) -> Duration {
+        Duration::from_nanos(self.resolution.as_nanos() * self.slices.len() as u64)
+    }
+
+    /// Returns the instant at which the oldest retained slice begins.
+    pub fn start_at(&self) -> Instant {
+        self.start + Duration::from_nanos(self.oldest() * self.resolution.as_nanos())
+    }
+
+    /// Returns the instant at which the newest slice ends.
+    pub fn end_at(&self) -> Instant {
+        self.start + Duration::from_nanos((self.tick + 1) * self.resolution.as_nanos())
+    }
+
+    /// Returns the number of slices which have been reached since the start,
+    /// up to the number of slices in the window.
+    pub(crate) fn active_slices(&self) -> usize {
+        (self.tick + 1 - self.oldest()) as usize
+    }
+
+    /// Returns the instant at which a slice begins along with its histogram,
+    /// counting the oldest retained slice as zero.
+    pub(crate) fn slice(&self, offset: usize) -> Option<(Instant, &Histogram)> {
+        if offset >= self.active_slices() {
+            return None;
+        }
+
+        let tick = self.oldest() + offset as u64;
+        let start = self.start + Duration::from_nanos(tick * self.resolution.as_nanos());
+
+        Some((start, &self.slices[self.slot(tick)]))
+    }
+
+    /// Converts an instant into the number of slices since the start.
+    fn tick_at(&self, time: Instant) -> Option<u64> {
+        time.checked_duration_since(self.start)
+            .map(|d| d.as_nanos() / self.resolution.as_nanos())
+    }
+
+    /// Returns the tick for the oldest retained slice.
+    fn oldest(&self) -> u64 {
+        (self.tick + 1).saturating_sub(self.slices.len() as u64)
+    }
+
+    /// Converts a tick into a position in the ring.
+    fn slot(&self, tick: u64) -> usize {
+        (tick % self.slices.len() as u64) as usize
+    }
+
+    /// Moves the newest slice forward to the provided tick, clearing any slices
+    /// which are reused.
+    fn advance(&mut self, tick: u64) {
+        if tick <= self.tick {
+            return;
+        }
+
+        let steps = (tick - self.tick).min(self.slices.len() as u64);
+
+        for t in (tick + 1 - steps)..=tick {
+            let slot = self.slot(t);
+            self.slices[slot].as_mut_slice().fill(0);
+        }
+
+        self.tick = tick;
+    }
+
+    /// Adds the counts from the slice at the provided tick into a histogram.
+    fn merge_into(&self, histogram: &mut Histogram, tick: u64) {
+        let slice = &self.slices[self.slot(tick)];
+
+        for (this, other) in histogram.as_mut_slice().iter_mut().zip(slice.as_slice()) {
+            *this = this.wrapping_add(*other);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build() -> (WindowedHistogram, Instant) {
+        let config = Config::new(7, 64).unwrap();
+        let start = Instant::now();
+        let histogram = WindowedHistogram::with_start(
+            &config,
+            Duration::from_secs(60),
+            Duration::from_secs(1),
+            start,
+        )
+        .unwrap();
+
+        (histogram, start)
+    }
+
+    #[test]
+    // Test that invalid window parameters are rejected
+    fn parameters() {
+        let config = Config::new(7, 64).unwrap();
+
+        assert_eq!(
+            WindowedHistogram::with_config(
+                &config,
+                Duration::from_secs(60),
+                Duration::from_secs(0)
+            )
+            .map(|_| ()),
+            Err(Error::IncompatibleParameters)
+        );
+        assert_eq!(
+            WindowedHistogram::with_config(&config, Duration::from_secs(1), Duration::from_secs(2))
+                .map(|_| ()),
+            Err(Error::IncompatibleParameters)
+        );
+        assert_eq!(
+            WindowedHistogram::with_config(&config, Duration::from_secs(5), Duration::from_secs(2))
+                .map(|_| ()),
+            Err(Error::IncompatibleParameters)
+        );
+    }
+
+    #[test]
+    // Test that slices rotate out of the window
+    fn rotation() {
+        let (mut histogram, start) = build();
+
+        for i in 0..120 {
+            histogram
+                .increment(start + Duration::from_secs(i), i as u64)
+                .unwrap();
+        }
+
+        let window = histogram.window();
+        assert_eq!(window.as_slice().iter().sum::<u64>(), 60);
+        assert_eq!(window.percentile(1.0).unwrap().unwrap().start(), 60);
+        assert_eq!(window.percentile(100.0).unwrap().unwrap().end(), 119);
+
+        assert_eq!(histogram.start_at(), start + Duration::from_secs(60));
+        assert_eq!(histogram.end_at(), start + Duration::from_secs(120));
+
+        // values older than the window are rejected
+        assert_eq!(
+            histogram.increment(start + Duration::from_secs(59), 1),
+            Err(Error::OutOfRange)
+        );
+    }
+
+    #[test]
+    // Test merged views over the most recent slices
+    fn last() {
+        let (mut histogram, start) = build();
+
+        for i in 0..10 {
+            histogram
+                .increment(start + Duration::from_secs(i), i as u64)
+                .unwrap();
+        }
+
+        let last = histogram.last(Duration::from_secs(3));
+        assert_eq!(last.as_slice().iter().sum::<u64>(), 3);
+        assert_eq!(last.percentile(1.0).unwrap().unwrap().start(), 7);
+
+        // skipping ahead clears the intermediate slices
+        histogram.advance_to(start + Duration::from_secs(65));
+        assert_eq!(histogram.window().as_slice().iter().sum::<u64>(), 4);
+
+        histogram.advance_to(start + Duration::from_secs(200));
+        assert_eq!(histogram.window().as_slice().iter().sum::<u64>(), 0);
+    }
+
+    #[test]
+    // Test merged views over arbitrary ranges
+    fn range() {
+        let (mut histogram, start) = build();
+
+        for i in 0..10 {
+            histogram
+                .increment(start + Duration::from_secs(i), i as u64)
+                .unwrap();
+        }
+
+        let range = histogram
+            .range(
+                start + Duration::from_secs(2),
+                start + Duration::from_secs(5),
+            )
+            .unwrap();
+        assert_eq!(range.as_slice().iter().sum::<u64>(), 3);
+        assert_eq!(range.percentile(1.0).unwrap().unwrap().start(), 2);
+        assert_eq!(range.percentile(100.0).unwrap().unwrap().end(), 4);
+
+        let range = histogram
+            .range(start, start + Duration::from_secs(1000))
+            .unwrap();
+        assert_eq!(range.as_slice().iter().sum::<u64>(), 10);
+
+        assert_eq!(
+            histogram.range(start + Duration::from_secs(5), start),
+            Err(Error::IncompatibleTimeRange)
+        );
+    }
+}
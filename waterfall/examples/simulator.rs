@@ -2,7 +2,8 @@
 // Licensed under the Apache License, Version 2.0
 // http://www.apache.org/licenses/LICENSE-2.0
 
-use heatmap::*;
+use clocksource::precise::{Duration, Instant};
+use histogram::Heatmap;
 use rand::thread_rng;
 use rand_distr::*;
 use waterfall::*;
@@ -38,16 +39,8 @@ pub fn simulate(shape: Shape) {
         duration.as_secs_f64()
     );
 
-    let heatmap = Heatmap::new(
-        0,
-        10,
-        30,
-        Duration::from_secs(10),
-        Duration::from_millis(250),
-        None,
-        None,
-    )
-    .unwrap();
+    let mut heatmap =
+        Heatmap::new(7, 30, Duration::from_secs(10), Duration::from_millis(250)).unwrap();
 
     let cauchy = Cauchy::new(500_000.0, 2_000.00).unwrap();
     let normal = Normal::new(200_000.0, 100_000.0).unwrap();
@@ -70,7 +63,7 @@ pub fn simulate(shape: Shape) {
         };
         let value = value.floor() as u64;
         if value != 0 {
-            let _ = heatmap.increment(Instant::now(), value, 1);
+            let _ = heatmap.increment(Instant::now(), value);
         }
     }
 
@@ -6,8 +6,9 @@
 
 mod palettes;
 
-use clocksource::{DateTime, Nanoseconds, UnixInstant};
-use heatmap::*;
+use clocksource::datetime::DateTime;
+use clocksource::precise::{Duration, Instant, UnixInstant};
+use histogram::Heatmap;
 pub use palettes::Palette;
 
 use image::*;
@@ -15,7 +16,6 @@ use palettes::*;
 use rusttype::{point, Font, PositionedGlyph, Scale as TypeScale};
 
 use std::collections::HashMap;
-use std::convert::TryInto;
 
 #[derive(Copy, Clone)]
 /// Used to configure various strategies for mapping values to colors
@@ -80,11 +80,11 @@ impl WaterfallBuilder {
     }
 
     // find the bucket with the highest weight
-    fn max_weight(&self, heatmap: &heatmap::Heatmap) -> f64 {
+    fn max_weight(&self, heatmap: &Heatmap) -> f64 {
         let mut max_weight = 0.0;
         for slice in heatmap {
             for b in slice {
-                let weight = self.weight(b.count().into(), b.high() - b.low() + 1);
+                let weight = self.weight(b.count(), b.end() - b.start() + 1);
                 if weight > max_weight {
                     max_weight = weight;
                 }
@@ -94,7 +94,7 @@ impl WaterfallBuilder {
     }
 
     /// Generate the waterfall from the provided heatmap
-    pub fn build(self, heatmap: &heatmap::Heatmap) {
+    pub fn build(self, heatmap: &Heatmap) {
         let height = heatmap.active_slices();
         let width = heatmap.buckets();
 
@@ -123,7 +123,7 @@ impl WaterfallBuilder {
             // build grayscale buffer
             for (y, slice) in heatmap.into_iter().enumerate() {
                 for (x, b) in slice.into_iter().enumerate() {
-                    let weight = self.weight(b.count().into(), b.high() - b.low() + 1);
+                    let weight = self.weight(b.count(), b.end() - b.start() + 1);
                     let scaled_weight = weight / max_weight;
                     let index = (scaled_weight * (colors.len() - 1) as f64).round() as u8;
                     buf.put_pixel(
@@ -149,7 +149,7 @@ impl WaterfallBuilder {
             // set the pixels in the buffer
             for (y, slice) in heatmap.into_iter().enumerate() {
                 for (x, b) in slice.into_iter().enumerate() {
-                    let weight = self.weight(b.count().into(), b.high() - b.low() + 1);
+                    let weight = self.weight(b.count(), b.end() - b.start() + 1);
                     let scaled_weight = weight / max_weight;
                     let index = (scaled_weight * (colors.len() - 1) as f64).round() as usize;
                     let color = colors[index];
@@ -166,7 +166,7 @@ impl WaterfallBuilder {
         if !label_keys.is_empty() {
             let slice = heatmap.into_iter().next().unwrap();
             for (x, bucket) in slice.into_iter().enumerate() {
-                let value = bucket.high();
+                let value = bucket.end();
                 if value >= label_keys[l] {
                     if let Some(label) = labels.get(&label_keys[l]) {
                         render_text(label, 25.0, x, 0, &mut buf);
@@ -186,18 +186,10 @@ impl WaterfallBuilder {
             }
         }
 
-        // add the timestamp labels along the left side
-        let now = UnixInstant::<Nanoseconds<u64>>::now();
-        let mut display_time = heatmap.start_at();
-        let ntick = (1 + now.duration_since(display_time).as_nanos()
-            / heatmap.resolution().as_nanos()) as usize;
-        if ntick > heatmap.active_slices() {
-            // heatmap only has partial history
-            // adjust earliest timestamp to display in Waterfall
-            display_time += heatmap
-                .resolution()
-                .mul_f64((ntick - heatmap.active_slices()) as f64);
-        }
+        // add the timestamp labels along the left side, converting the
+        // monotonic start of the oldest slice to the wall clock
+        let mut display_time =
+            UnixInstant::now() - Instant::now().duration_since(heatmap.start_at());
 
         for (y, _) in heatmap.into_iter().enumerate() {
             if heatmap.resolution().as_nanos() >= self.interval.as_nanos() {